@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use ndarray::{Array1, Array2};
+
+use crate::{linalg::LuDecomposition, mdp::MDP, policy::StochasticPolicy, tabular::TabularMDP};
+
+/// How episodes that follow a fixed policy end, from treating the MDP under
+/// the policy as an absorbing Markov chain. States without actions, such as
+/// terminal states, absorb; every other state is transient.
+#[derive(Debug, Clone)]
+pub struct AbsorbingChain<S> {
+    /// The probability of ending in each absorbing state, by starting state
+    pub absorption_probs: HashMap<S, HashMap<S, f64>>,
+    /// Expected number of steps until absorption, by starting state
+    pub expected_steps: HashMap<S, f64>,
+    /// Variance of the number of steps until absorption, by starting state
+    pub steps_variance: HashMap<S, f64>,
+    /// The probability of ending in each absorbing state, starting from the
+    /// initial distribution
+    pub terminal_probs: HashMap<S, f64>,
+    /// Expected episode length, starting from the initial distribution
+    pub expected_length: f64,
+    /// Variance of the episode length, starting from the initial distribution
+    pub length_variance: f64,
+}
+
+impl<S: Copy + std::hash::Hash + Eq> AbsorbingChain<S> {
+    /// The probability that an episode from the initial distribution ends in
+    /// any of `terminals`, e.g. the success rate when they are the goals.
+    pub fn probability_of(&self, terminals: &[S]) -> f64 {
+        terminals
+            .iter()
+            .filter_map(|terminal| self.terminal_probs.get(terminal))
+            .sum()
+    }
+}
+
+/// Absorption probabilities and episode lengths of `policy`. With Q the
+/// transitions among transient states and R those into absorbing ones, the
+/// fundamental matrix N = (I - Q)⁻¹ gives absorption probabilities NR,
+/// expected steps t = N1 and their variance (2N - I)t - t². Fails if some
+/// episode can go on forever, which makes I - Q singular.
+pub fn absorbing_chain<M, P>(mdp: &M, policy: &P) -> Result<AbsorbingChain<M::State>, String>
+where
+    M: MDP,
+    P: StochasticPolicy<M::State, M::Action>,
+{
+    let tabular = TabularMDP::from_mdp(mdp);
+    let policy = tabular.policy_rows(policy);
+    let states = tabular.states();
+
+    let (transient, absorbing): (Vec<usize>, Vec<usize>) =
+        (0..tabular.num_states()).partition(|&s| !tabular.rows(s).is_empty());
+    let mut positions = vec![0; tabular.num_states()];
+    for (i, &s) in transient.iter().enumerate() {
+        positions[s] = i;
+    }
+    for (k, &s) in absorbing.iter().enumerate() {
+        positions[s] = k;
+    }
+
+    // I - Q and R
+    let n = transient.len();
+    let mut a = Array2::<f64>::eye(n);
+    let mut r = Array2::<f64>::zeros((n, absorbing.len()));
+    for (i, &s) in transient.iter().enumerate() {
+        for &(row, action_prob) in &policy[s] {
+            let (next_states, probs) = tabular.successors(row);
+            for (&j, &prob) in next_states.iter().zip(probs) {
+                if tabular.rows(j).is_empty() {
+                    r[[i, positions[j]]] += action_prob * prob;
+                } else {
+                    a[[i, positions[j]]] -= action_prob * prob;
+                }
+            }
+        }
+    }
+
+    let lu = LuDecomposition::new(a)
+        .map_err(|e| format!("some episodes under the policy never end: {}", e))?;
+    let steps = lu.solve(&Array1::ones(n));
+    let second_moments = 2.0 * lu.solve(&steps) - &steps;
+    let absorption: Vec<Array1<f64>> = r
+        .columns()
+        .into_iter()
+        .map(|column| lu.solve(&column.to_owned()))
+        .collect();
+
+    let mut absorption_probs = HashMap::new();
+    let mut expected_steps = HashMap::new();
+    let mut steps_variance = HashMap::new();
+    for (i, &s) in transient.iter().enumerate() {
+        let probs = absorbing
+            .iter()
+            .zip(&absorption)
+            .map(|(&t, column)| (states[t], column[i]))
+            .collect();
+        absorption_probs.insert(states[s], probs);
+        expected_steps.insert(states[s], steps[i]);
+        steps_variance.insert(
+            states[s],
+            (second_moments[i] - steps[i] * steps[i]).max(0.0),
+        );
+    }
+    for &t in &absorbing {
+        absorption_probs.insert(states[t], HashMap::from([(states[t], 1.0)]));
+        expected_steps.insert(states[t], 0.0);
+        steps_variance.insert(states[t], 0.0);
+    }
+
+    let mut terminal_probs: HashMap<M::State, f64> =
+        absorbing.iter().map(|&t| (states[t], 0.0)).collect();
+    let mut expected_length = 0.0;
+    let mut second_moment = 0.0;
+    for (state, prob) in mdp.initial_distribution() {
+        let Some(s) = tabular.state_index(&state) else {
+            continue;
+        };
+        for (terminal, absorption_prob) in &absorption_probs[&state] {
+            *terminal_probs.get_mut(terminal).unwrap() += prob * absorption_prob;
+        }
+        if !tabular.rows(s).is_empty() {
+            let i = positions[s];
+            expected_length += prob * steps[i];
+            second_moment += prob * second_moments[i];
+        }
+    }
+
+    Ok(AbsorbingChain {
+        absorption_probs,
+        expected_steps,
+        steps_variance,
+        terminal_probs,
+        expected_length,
+        length_variance: (second_moment - expected_length * expected_length).max(0.0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::Reward,
+        generate_episodes,
+        grid_world::{GridWorld, GridWorldEnv, GridWorldMDP, FROZEN_LAKE_4X4, FROZEN_LAKE_8X8},
+        mdp::Probability,
+        policy::MDPPolicy,
+        policy_iteration::{evaluate_policy_exact, value_iteration},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Gambler's ruin: a fair coin moves between 0 and 4, which end the game.
+    struct Ruin;
+
+    impl MDP for Ruin {
+        type State = u8;
+        type Action = ();
+
+        fn get_states(&self) -> &[u8] {
+            &[0, 1, 2, 3, 4]
+        }
+        fn get_actions(&self) -> &[()] {
+            &[()]
+        }
+        fn transition(&self, state: u8, _action: ()) -> &[(u8, Probability)] {
+            match state {
+                1 => &[(0, 0.5), (2, 0.5)],
+                2 => &[(1, 0.5), (3, 0.5)],
+                3 => &[(2, 0.5), (4, 0.5)],
+                _ => &[],
+            }
+        }
+        fn reward(&self, _state: u8, _action: (), _next_state: u8) -> Reward {
+            0.0
+        }
+        fn initial_distribution(&self) -> Vec<(u8, Probability)> {
+            vec![(1, 0.5), (2, 0.5)]
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn gamblers_ruin() {
+        let policy = MDPPolicy::<Ruin>::new(HashMap::from([(1, ()), (2, ()), (3, ())]));
+        let chain = absorbing_chain(&Ruin, &policy).unwrap();
+
+        for i in 1..=3u8 {
+            let i_f = i as f64;
+            assert!(close(chain.absorption_probs[&i][&4], i_f / 4.0));
+            assert!(close(chain.expected_steps[&i], i_f * (4.0 - i_f)));
+        }
+        // Var = i(N - i)((N - i)² + i² - 2) / 3
+        assert!(close(
+            chain.steps_variance[&1],
+            3.0 * (9.0 + 1.0 - 2.0) / 3.0
+        ));
+        assert!(close(
+            chain.steps_variance[&2],
+            4.0 * (4.0 + 4.0 - 2.0) / 3.0
+        ));
+        assert!(close(chain.expected_steps[&0], 0.0));
+
+        assert!(close(chain.probability_of(&[4]), 0.5 * 0.25 + 0.5 * 0.5));
+        assert!(close(chain.expected_length, 0.5 * 3.0 + 0.5 * 4.0));
+        // mixture of lengths 3 and 4 with variances 8 and 8 adds 0.25 between them
+        assert!(close(chain.length_variance, 8.0 + 0.25));
+    }
+
+    #[test]
+    fn frozen_lake_success_rates() {
+        for (map, goal) in [(&FROZEN_LAKE_4X4[..], 15), (&FROZEN_LAKE_8X8[..], 63)] {
+            let mdp = GridWorldMDP::new(GridWorld::from_map(map, 2.0 / 3.0, 0.99).unwrap());
+            let policy = value_iteration(&mdp, 0.99, 1e-10).unwrap().policy;
+            let chain = absorbing_chain(&mdp, &policy).unwrap();
+
+            // the undiscounted value of the start is its success rate
+            let success = chain.probability_of(&[goal]);
+            let values = evaluate_policy_exact(&mdp, &policy, 1.0).unwrap();
+            assert!(close(success, values[&0]));
+            assert!(close(chain.terminal_probs.values().sum(), 1.0));
+
+            let mut env = GridWorldEnv::new(mdp, StdRng::seed_from_u64(0));
+            let rewards = generate_episodes(&mut env, &policy, 10000, 0);
+            let estimate = rewards.iter().sum::<f64>() / rewards.len() as f64;
+            assert!((estimate - success).abs() < 0.02);
+        }
+    }
+}
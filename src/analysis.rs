@@ -0,0 +1,303 @@
+use std::{collections::HashSet, hash::Hash};
+
+use crate::{
+    environment::Reward,
+    mdp::{Probability, MDP},
+    tabular::TabularMDP,
+};
+
+/// The transition graph of `tabular` over state indices: an edge from s to
+/// every successor some available action reaches with positive probability.
+fn transition_graph<S, A>(tabular: &TabularMDP<S, A>) -> Vec<Vec<usize>>
+where
+    S: Copy + Hash + Eq,
+    A: Copy + Hash + Eq,
+{
+    (0..tabular.num_states())
+        .map(|s| {
+            let mut successors: Vec<usize> = tabular
+                .rows(s)
+                .flat_map(|row| {
+                    let (next_states, probs) = tabular.successors(row);
+                    next_states
+                        .iter()
+                        .zip(probs)
+                        .filter(|&(_, &prob)| prob > 0.0)
+                        .map(|(&j, _)| j)
+                })
+                .collect();
+            successors.sort_unstable();
+            successors.dedup();
+            successors
+        })
+        .collect()
+}
+
+/// Every node reachable from `starts`, including the starts themselves.
+fn search(graph: &[Vec<usize>], starts: impl IntoIterator<Item = usize>) -> Vec<bool> {
+    let mut seen = vec![false; graph.len()];
+    let mut stack = vec![];
+    for start in starts {
+        if !seen[start] {
+            seen[start] = true;
+            stack.push(start);
+        }
+    }
+    while let Some(i) = stack.pop() {
+        for &j in &graph[i] {
+            if !seen[j] {
+                seen[j] = true;
+                stack.push(j);
+            }
+        }
+    }
+    seen
+}
+
+fn states_where<M: MDP>(mdp: &M, flags: &[bool]) -> Vec<M::State> {
+    mdp.get_states()
+        .iter()
+        .zip(flags)
+        .filter(|&(_, &flag)| flag)
+        .map(|(&state, _)| state)
+        .collect()
+}
+
+/// Strongly connected components of a graph given by the successors of each
+/// node, with Tarjan's algorithm. Components come out in reverse topological
+/// order, so no component has an edge to a later one, and each is sorted.
+pub(crate) fn components(successors: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    const UNVISITED: usize = usize::MAX;
+    let n = successors.len();
+    let mut index = vec![UNVISITED; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = vec![];
+    let mut components = vec![];
+    let mut next_index = 0;
+
+    for root in 0..n {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        index[root] = next_index;
+        low[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        // explicit call stack of nodes and the position of their next successor
+        let mut calls = vec![(root, 0)];
+        while let Some((v, i)) = calls.last_mut() {
+            let v = *v;
+            if let Some(&w) = successors[v].get(*i) {
+                *i += 1;
+                if index[w] == UNVISITED {
+                    index[w] = next_index;
+                    low[w] = next_index;
+                    next_index += 1;
+                    stack.push(w);
+                    on_stack[w] = true;
+                    calls.push((w, 0));
+                } else if on_stack[w] {
+                    low[v] = low[v].min(index[w]);
+                }
+            } else {
+                calls.pop();
+                if let Some(&(parent, _)) = calls.last() {
+                    low[parent] = low[parent].min(low[v]);
+                }
+                if low[v] == index[v] {
+                    let mut component = vec![];
+                    loop {
+                        let w = stack.pop().expect("v is on the stack");
+                        on_stack[w] = false;
+                        component.push(w);
+                        if w == v {
+                            break;
+                        }
+                    }
+                    component.sort_unstable();
+                    components.push(component);
+                }
+            }
+        }
+    }
+    components
+}
+
+/// Strongly connected components of the transition graph of `mdp`, where
+/// a state links to every successor of its available actions. Components
+/// come in reverse topological order: none can reach a later one.
+pub fn strongly_connected_components<M: MDP>(mdp: &M) -> Vec<Vec<M::State>> {
+    let states = mdp.get_states();
+    components(&transition_graph(&TabularMDP::from_mdp(mdp)))
+        .into_iter()
+        .map(|component| component.into_iter().map(|s| states[s]).collect())
+        .collect()
+}
+
+/// The states some policy can visit starting from the initial distribution,
+/// in the order of `get_states`.
+pub fn reachable_states<M: MDP>(mdp: &M) -> Vec<M::State> {
+    let tabular = TabularMDP::from_mdp(mdp);
+    let starts: Vec<usize> = mdp
+        .initial_distribution()
+        .into_iter()
+        .filter(|&(_, prob)| prob > 0.0)
+        .filter_map(|(state, _)| tabular.state_index(&state))
+        .collect();
+    states_where(mdp, &search(&transition_graph(&tabular), starts))
+}
+
+/// The states from which no policy ever collects a non-zero reward. Their
+/// value is zero under every policy.
+pub fn states_without_reward<M: MDP>(mdp: &M) -> Vec<M::State> {
+    let graph = transition_graph(&TabularMDP::from_mdp(mdp));
+    let mut predecessors = vec![vec![]; graph.len()];
+    for (s, successors) in graph.iter().enumerate() {
+        for &j in successors {
+            predecessors[j].push(s);
+        }
+    }
+
+    let rewarding = mdp.get_states().iter().enumerate().filter(|&(_, &state)| {
+        !mdp.is_terminal(state)
+            && mdp.available_actions(state).iter().any(|&action| {
+                mdp.transition(state, action).iter().any(
+                    |&(next_state, prob): &(M::State, Probability)| {
+                        let reward: Reward = mdp.reward(state, action, next_state);
+                        prob > 0.0 && reward != 0.0
+                    },
+                )
+            })
+    });
+    let can_reach_reward = search(&predecessors, rewarding.map(|(s, _)| s));
+    let never: Vec<bool> = can_reach_reward.iter().map(|&can| !can).collect();
+    states_where(mdp, &never)
+}
+
+/// The states that are never left once entered: terminal states, and states
+/// where every available action leads back to the state itself.
+pub fn absorbing_states<M: MDP>(mdp: &M) -> Vec<M::State> {
+    let graph = transition_graph(&TabularMDP::from_mdp(mdp));
+    let absorbing: Vec<bool> = graph
+        .iter()
+        .enumerate()
+        .map(|(s, successors)| successors.iter().all(|&j| j == s))
+        .collect();
+    states_where(mdp, &absorbing)
+}
+
+/// An MDP limited to a subset of the states of another, e.g. the states
+/// reachable from the initial distribution, so that solvers skip the rest.
+/// Successors outside the subset are treated like unknown states by the
+/// solvers, so the subset should be closed under transitions.
+pub struct Restricted<'a, M: MDP> {
+    mdp: &'a M,
+    states: Vec<M::State>,
+}
+
+impl<'a, M: MDP> Restricted<'a, M> {
+    pub fn new(mdp: &'a M, states: Vec<M::State>) -> Self {
+        Self { mdp, states }
+    }
+
+    /// The states reachable from the initial distribution, which are closed
+    /// under transitions.
+    pub fn reachable(mdp: &'a M) -> Self {
+        Self::new(mdp, reachable_states(mdp))
+    }
+}
+
+impl<M: MDP> MDP for Restricted<'_, M> {
+    type State = M::State;
+    type Action = M::Action;
+
+    fn get_states(&self) -> &[Self::State] {
+        &self.states
+    }
+
+    fn get_actions(&self) -> &[Self::Action] {
+        self.mdp.get_actions()
+    }
+
+    fn available_actions(&self, state: Self::State) -> &[Self::Action] {
+        self.mdp.available_actions(state)
+    }
+
+    fn transition(
+        &self,
+        state: Self::State,
+        action: Self::Action,
+    ) -> &[(Self::State, Probability)] {
+        self.mdp.transition(state, action)
+    }
+
+    fn reward(&self, state: Self::State, action: Self::Action, next_state: Self::State) -> Reward {
+        self.mdp.reward(state, action, next_state)
+    }
+
+    fn is_terminal(&self, state: Self::State) -> bool {
+        self.mdp.is_terminal(state)
+    }
+
+    fn initial_distribution(&self) -> Vec<(Self::State, Probability)> {
+        let states: HashSet<M::State> = self.states.iter().copied().collect();
+        self.mdp
+            .initial_distribution()
+            .into_iter()
+            .filter(|(state, _)| states.contains(state))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid_world::{GridWorld, GridWorldMDP, FROZEN_LAKE_8X8};
+    use crate::policy_iteration::value_iteration;
+
+    #[test]
+    fn tarjan_components() {
+        // 0 <-> 1 -> 2 <-> 3 -> 4, and 5 on its own pointing at 0
+        let graph = vec![vec![1], vec![0, 2], vec![3], vec![2, 4], vec![], vec![0]];
+        assert_eq!(
+            components(&graph),
+            vec![vec![4], vec![2, 3], vec![0, 1], vec![5]]
+        );
+    }
+
+    #[test]
+    fn grid_world_structure() {
+        let grid_world = GridWorld::from_map(&["SFFG", "HHHH", "FFFF", "FFFF"], 0.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+
+        // the hole below the goal can only be entered from the goal or other holes
+        assert_eq!(reachable_states(&mdp), (0..7).collect::<Vec<_>>());
+        assert_eq!(absorbing_states(&mdp), (3..8).collect::<Vec<_>>());
+        // the bottom half can only walk into holes
+        assert_eq!(states_without_reward(&mdp), (3..16).collect::<Vec<_>>());
+
+        let components = strongly_connected_components(&mdp);
+        assert!(components.contains(&vec![0, 1, 2]));
+        assert!(components.contains(&vec![8, 9, 10, 11, 12, 13, 14, 15]));
+
+        let restricted = Restricted::reachable(&mdp);
+        let full = value_iteration(&mdp, 0.9, 1e-10).unwrap();
+        let solution = value_iteration(&restricted, 0.9, 1e-10).unwrap();
+        assert_eq!(solution.state_values.len(), 7);
+        for state in restricted.get_states() {
+            assert!((solution.state_value(state) - full.state_value(state)).abs() < 1e-9);
+        }
+        assert_eq!(solution.expected_return, full.expected_return);
+    }
+
+    #[test]
+    fn frozen_lake_is_connected_to_its_goal() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 2.0 / 3.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        assert!(reachable_states(&mdp).contains(&63));
+        assert_eq!(states_without_reward(&mdp).len(), 10 + 1);
+    }
+}
@@ -0,0 +1,557 @@
+use std::collections::HashMap;
+
+use ndarray::{Array1, Array2};
+
+use crate::{
+    analysis, linalg,
+    mdp::MDP,
+    policy::{MDPPolicy, Policy},
+    policy_iteration::{self, Improvement},
+    stopping::{ConvergenceError, StopReason, StoppingCriteria},
+    tabular::TabularMDP,
+};
+
+/// Weight on the original dynamics in the aperiodicity transform
+/// P̃ = τP + (1 - τ)I, which leaves gain and bias unchanged but makes
+/// relative value iteration converge on periodic chains.
+const APERIODICITY_WEIGHT: f64 = 0.5;
+
+/// Optimal long-run behaviour of a continuing task.
+pub struct AverageRewardSolution<M: MDP> {
+    pub policy: MDPPolicy<M>,
+    /// Long-run average reward per step
+    pub gain: f64,
+    /// Relative value of each state, normalized to zero at the first state
+    pub bias: HashMap<M::State, f64>,
+    pub num_iterations: usize,
+}
+
+/// A converged solution, or the last iterate of a run that ran out of budget
+/// or met a policy with more than one recurrent class
+pub type AverageRewardResult<M> =
+    Result<AverageRewardSolution<M>, ConvergenceError<AverageRewardSolution<M>>>;
+
+impl<M: MDP> AverageRewardSolution<M> {
+    /// What a run that fails before evaluating `policy` reports: NaN gain and bias.
+    fn unevaluated(mdp: &M, policy: MDPPolicy<M>, num_iterations: usize) -> Self {
+        Self {
+            policy,
+            gain: f64::NAN,
+            bias: mdp.get_states().iter().map(|&s| (s, f64::NAN)).collect(),
+            num_iterations,
+        }
+    }
+
+    fn failed(self, message: String) -> ConvergenceError<Self> {
+        ConvergenceError {
+            reason: StopReason::Failed(message),
+            num_iterations: self.num_iterations,
+            last_iterate: Box::new(self),
+        }
+    }
+}
+
+/// Q(s, a) = r(s, a) + Σ P(s' | s, a) h(s'). States with no transitions are
+/// treated as absorbing with zero reward.
+fn action_value<M>(
+    mdp: &M,
+    state: M::State,
+    action: M::Action,
+    bias: &HashMap<M::State, f64>,
+) -> f64
+where
+    M: MDP,
+{
+    let transitions = mdp.transition(state, action);
+    if transitions.is_empty() {
+        return bias[&state];
+    }
+    transitions
+        .iter()
+        .map(|&(next_state, prob)| {
+            let reward = mdp.reward(state, action, next_state);
+            prob * (reward + bias.get(&next_state).unwrap_or(&0.0))
+        })
+        .sum()
+}
+
+/// Q of every row of `tabular`, in row order.
+fn action_values<M>(
+    mdp: &M,
+    tabular: &TabularMDP<M::State, M::Action>,
+    bias: &HashMap<M::State, f64>,
+) -> Vec<f64>
+where
+    M: MDP,
+{
+    tabular
+        .states()
+        .iter()
+        .enumerate()
+        .flat_map(|(s, &state)| {
+            tabular
+                .rows(s)
+                .map(move |row| action_value(mdp, state, tabular.row_action(row), bias))
+        })
+        .collect()
+}
+
+/// Relative value iteration for unichain MDPs. Iterates the (aperiodicity
+/// transformed) Bellman operator, subtracting the value of the first state
+/// each sweep, until the span of the change meets the tolerance in
+/// `stopping`. Fails if the budget runs out first, or with
+/// [`StopReason::Failed`] if the resulting policy has more than one
+/// recurrent class. The latter is reported even if the budget ran out too,
+/// as it will on many multichain MDPs, whose states settle to different gains.
+pub fn relative_value_iteration<M>(
+    mdp: &M,
+    stopping: impl Into<StoppingCriteria>,
+) -> AverageRewardResult<M>
+where
+    M: MDP,
+{
+    let states = mdp.get_states();
+    let no_policy = || MDPPolicy::new(HashMap::new());
+    let Some(&reference) = states.first() else {
+        return Err(AverageRewardSolution::unevaluated(mdp, no_policy(), 0)
+            .failed("MDP has no states".into()));
+    };
+    check_actions(mdp).map_err(|message| {
+        AverageRewardSolution::unevaluated(mdp, no_policy(), 0).failed(message)
+    })?;
+    let tabular = TabularMDP::from_mdp(mdp);
+    let tau = APERIODICITY_WEIGHT;
+    let stopper = stopping.into().start(1.0);
+
+    let mut bias: HashMap<M::State, f64> = states.iter().map(|&s| (s, 0.0)).collect();
+    let mut num_iterations = 0;
+
+    loop {
+        num_iterations += 1;
+
+        let (_, state_values) = tabular.greedy(&action_values(mdp, &tabular, &bias));
+        let updated: HashMap<M::State, f64> = states
+            .iter()
+            .enumerate()
+            .map(|(s, &state)| {
+                // terminal states are absorbing with zero reward
+                let value = if tabular.rows(s).is_empty() {
+                    bias[&state]
+                } else {
+                    state_values[s]
+                };
+                (state, tau * value + (1.0 - tau) * bias[&state])
+            })
+            .collect();
+
+        // the change in each state brackets the gain (scaled by τ)
+        let (min_diff, max_diff) =
+            states
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), s| {
+                    let diff = updated[s] - bias[s];
+                    (lo.min(diff), hi.max(diff))
+                });
+
+        let offset = updated[&reference];
+        bias = updated.into_iter().map(|(s, v)| (s, v - offset)).collect();
+
+        let status = stopper.status(num_iterations, max_diff - min_diff);
+        if !status.is_running() {
+            let (policy_rows, _) = tabular.greedy(&action_values(mdp, &tabular, &bias));
+            let policy = tabular.to_policy(&policy_rows);
+            let multichain = check_unichain(mdp, &policy);
+            let solution = AverageRewardSolution {
+                policy,
+                gain: (max_diff + min_diff) / (2.0 * tau),
+                bias,
+                num_iterations,
+            };
+            return match multichain {
+                Ok(()) => status.into_result(num_iterations, solution),
+                Err(message) => Err(solution.failed(message)),
+            };
+        }
+    }
+}
+
+/// Average-reward policy iteration for unichain MDPs with the default
+/// [`Improvement`], see [`average_reward_policy_iteration_with`].
+pub fn average_reward_policy_iteration<M>(
+    mdp: &M,
+    stopping: impl Into<StoppingCriteria>,
+) -> AverageRewardResult<M>
+where
+    M: MDP,
+{
+    average_reward_policy_iteration_with(mdp, stopping, Improvement::default())
+}
+
+/// Average-reward policy iteration for unichain MDPs. Each policy's gain and
+/// bias are found exactly from g + h(s) = r_π(s) + Σ P_π(s' | s) h(s') with
+/// h fixed to zero at the first state, and the policy is improved on the
+/// bias as [`policy_iteration_with`](policy_iteration::policy_iteration_with)
+/// improves it on the values. With [`TieBreak::All`](policy_iteration::TieBreak::All)
+/// the policy takes the first of the tied actions. Only the iteration and
+/// time budgets of `stopping` apply, as evaluation is exact; without either
+/// the run is capped at [`DEFAULT_MAX_ITERATIONS`](crate::stopping::DEFAULT_MAX_ITERATIONS)
+/// improvement steps. Fails with [`StopReason::Failed`] as soon as a policy
+/// with more than one recurrent class is encountered.
+pub fn average_reward_policy_iteration_with<M>(
+    mdp: &M,
+    stopping: impl Into<StoppingCriteria>,
+    improvement: Improvement,
+) -> AverageRewardResult<M>
+where
+    M: MDP,
+{
+    let states = mdp.get_states();
+    let no_policy = || MDPPolicy::new(HashMap::new());
+    if states.is_empty() {
+        return Err(AverageRewardSolution::unevaluated(mdp, no_policy(), 0)
+            .failed("MDP has no states".into()));
+    }
+    check_actions(mdp).map_err(|message| {
+        AverageRewardSolution::unevaluated(mdp, no_policy(), 0).failed(message)
+    })?;
+    let stopper = stopping.into().start(1.0);
+    let tabular = TabularMDP::from_mdp(mdp);
+    let mut tie_rng = improvement.tie_rng();
+
+    // start from the policy that is greedy for immediate reward
+    let zero: HashMap<M::State, f64> = states.iter().map(|&s| (s, 0.0)).collect();
+    let (mut policy_rows, _) = policy_iteration::improve(
+        &tabular,
+        &action_values(mdp, &tabular, &zero),
+        &vec![vec![]; tabular.num_states()],
+        &improvement,
+        0.0,
+        &mut tie_rng,
+    );
+
+    let mut num_iterations = 0;
+    loop {
+        num_iterations += 1;
+
+        let first_rows: Vec<Option<usize>> = policy_rows
+            .iter()
+            .map(|rows| rows.first().copied())
+            .collect();
+        let policy = tabular.to_policy(&first_rows);
+        let evaluated =
+            check_unichain(mdp, &policy).and_then(|()| evaluate_gain_bias(mdp, &policy));
+        let (gain, bias) = match evaluated {
+            Ok(evaluated) => evaluated,
+            Err(message) => {
+                return Err(
+                    AverageRewardSolution::unevaluated(mdp, policy, num_iterations).failed(message),
+                )
+            }
+        };
+
+        let (new_policy_rows, _) = policy_iteration::improve(
+            &tabular,
+            &action_values(mdp, &tabular, &bias),
+            &policy_rows,
+            &improvement,
+            0.0,
+            &mut tie_rng,
+        );
+        let converged = new_policy_rows == policy_rows;
+        let exhausted = if converged {
+            None
+        } else {
+            stopper.exhausted(num_iterations)
+        };
+        if converged || exhausted.is_some() {
+            let solution = AverageRewardSolution {
+                policy,
+                gain,
+                bias,
+                num_iterations,
+            };
+            return match exhausted {
+                None => Ok(solution),
+                Some(reason) => Err(ConvergenceError {
+                    reason,
+                    num_iterations,
+                    last_iterate: Box::new(solution),
+                }),
+            };
+        }
+        policy_rows = new_policy_rows;
+    }
+}
+
+/// Gain and bias of a unichain policy, with the bias of the first state fixed at zero.
+pub fn evaluate_gain_bias<M, P>(
+    mdp: &M,
+    policy: &P,
+) -> Result<(f64, HashMap<M::State, f64>), String>
+where
+    M: MDP,
+    P: Policy<M::State, M::Action>,
+{
+    let states = mdp.get_states();
+    let (transitions, rewards) = chain_matrices(mdp, policy);
+    let n = states.len();
+
+    // unknowns are h(s) for every state except the first, whose column holds g
+    let mut a = Array2::<f64>::eye(n) - transitions;
+    a.column_mut(0).fill(1.0);
+    let solution = linalg::solve(a, &rewards)?;
+
+    let gain = solution[0];
+    let bias = states
+        .iter()
+        .enumerate()
+        .map(|(i, &s)| (s, if i == 0 { 0.0 } else { solution[i] }))
+        .collect();
+    Ok((gain, bias))
+}
+
+/// Transition matrix and expected rewards of the Markov chain induced by a
+/// policy, indexed by position in `get_states`. Terminal states and states
+/// with no transitions become absorbing.
+fn chain_matrices<M, P>(mdp: &M, policy: &P) -> (Array2<f64>, Array1<f64>)
+where
+    M: MDP,
+    P: Policy<M::State, M::Action>,
+{
+    let states = mdp.get_states();
+    let state_indices: HashMap<M::State, usize> =
+        states.iter().enumerate().map(|(i, &s)| (s, i)).collect();
+
+    let n = states.len();
+    let mut transitions = Array2::zeros((n, n));
+    let mut rewards = Array1::zeros(n);
+    for (i, &state) in states.iter().enumerate() {
+        if mdp.is_terminal(state) {
+            transitions[[i, i]] = 1.0;
+            continue;
+        }
+        let action = policy.get_action(&state);
+        let next_states = mdp.transition(state, action);
+        if next_states.is_empty() {
+            transitions[[i, i]] = 1.0;
+        }
+        for &(next_state, prob) in next_states {
+            rewards[i] += prob * mdp.reward(state, action, next_state);
+            if let Some(&j) = state_indices.get(&next_state) {
+                transitions[[i, j]] += prob;
+            }
+        }
+    }
+    (transitions, rewards)
+}
+
+/// Every non-terminal state needs an action to keep the chain running.
+fn check_actions<M>(mdp: &M) -> Result<(), String>
+where
+    M: MDP,
+{
+    if mdp
+        .get_states()
+        .iter()
+        .any(|&state| !mdp.is_terminal(state) && mdp.available_actions(state).is_empty())
+    {
+        return Err("average reward needs an available action in every non-terminal state".into());
+    }
+    Ok(())
+}
+
+fn check_unichain<M, P>(mdp: &M, policy: &P) -> Result<(), String>
+where
+    M: MDP,
+    P: Policy<M::State, M::Action>,
+{
+    let (transitions, _) = chain_matrices(mdp, policy);
+    let num_classes = count_recurrent_classes(&transitions);
+    if num_classes > 1 {
+        Err(format!(
+            "MDP is multichain: a policy has {} recurrent classes",
+            num_classes
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Recurrent classes are the closed strongly connected components of the chain.
+fn count_recurrent_classes(transitions: &Array2<f64>) -> usize {
+    let n = transitions.nrows();
+    let successors: Vec<Vec<usize>> = (0..n)
+        .map(|i| (0..n).filter(|&j| transitions[[i, j]] > 0.0).collect())
+        .collect();
+
+    analysis::components(&successors)
+        .iter()
+        .filter(|component| {
+            component
+                .iter()
+                .all(|&i| successors[i].iter().all(|j| component.contains(j)))
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::Reward;
+    use crate::grid_world::{GridWorld, GridWorldMDP, FROZEN_LAKE_4X4};
+    use crate::mdp::Probability;
+    use crate::policy_iteration::TieBreak;
+
+    const STAY: u8 = 0;
+    const MOVE: u8 = 1;
+
+    /// Two states: idling in state 0 pays 1 per step; state 1 pays 2 per step
+    /// but leaks back to state 0 with probability 0.1.
+    struct Continuing {
+        states: Vec<u8>,
+        actions: Vec<u8>,
+        transitions: HashMap<(u8, u8), Vec<(u8, Probability)>>,
+    }
+
+    impl Continuing {
+        fn new() -> Self {
+            let transitions = HashMap::from([
+                ((0, STAY), vec![(0, 1.0)]),
+                ((0, MOVE), vec![(1, 1.0)]),
+                ((1, STAY), vec![(1, 0.9), (0, 0.1)]),
+                ((1, MOVE), vec![(0, 1.0)]),
+            ]);
+            Self {
+                states: vec![0, 1],
+                actions: vec![STAY, MOVE],
+                transitions,
+            }
+        }
+    }
+
+    impl MDP for Continuing {
+        type State = u8;
+        type Action = u8;
+
+        fn get_states(&self) -> &[u8] {
+            &self.states
+        }
+        fn get_actions(&self) -> &[u8] {
+            &self.actions
+        }
+        fn transition(&self, state: u8, action: u8) -> &[(u8, Probability)] {
+            &self.transitions[&(state, action)]
+        }
+        fn reward(&self, state: u8, action: u8, next_state: u8) -> Reward {
+            match (state, action, next_state) {
+                (0, STAY, _) => 1.0,
+                (1, STAY, 1) => 2.0,
+                (1, STAY, 0) => 2.0,
+                _ => 0.0,
+            }
+        }
+    }
+
+    fn assert_optimal(solution: &AverageRewardSolution<Continuing>) {
+        assert!((solution.gain - 20.0 / 11.0).abs() < 1e-6);
+        assert_eq!(solution.bias[&0], 0.0);
+        assert!((solution.bias[&1] - 20.0 / 11.0).abs() < 1e-6);
+        assert_eq!(solution.policy.get_action(&0), MOVE);
+        assert_eq!(solution.policy.get_action(&1), STAY);
+    }
+
+    #[test]
+    fn relative_value_iteration_finds_gain_and_bias() {
+        assert_optimal(&relative_value_iteration(&Continuing::new(), 1e-10).unwrap());
+    }
+
+    #[test]
+    fn policy_iteration_finds_gain_and_bias() {
+        assert_optimal(&average_reward_policy_iteration(&Continuing::new(), 1e-10).unwrap());
+    }
+
+    #[test]
+    fn policy_iteration_uses_the_improvement_settings() {
+        let mdp = Continuing::new();
+        let improvement = Improvement::default().with_tie_break(TieBreak::Random { seed: 3 });
+        assert_optimal(&average_reward_policy_iteration_with(&mdp, 1e-10, improvement).unwrap());
+
+        // nothing beats staying by more than the tolerance
+        let improvement = Improvement::default().with_tolerance(10.0);
+        let solution = average_reward_policy_iteration_with(&mdp, 1e-10, improvement).unwrap();
+        assert_eq!(solution.num_iterations, 1);
+        assert_eq!(solution.gain, 1.0);
+        assert_eq!(solution.policy.get_action(&1), STAY);
+    }
+
+    #[test]
+    fn multichain_is_reported() {
+        // every hole and the goal is its own absorbing class
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 0.0, 1.0).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        for error in [
+            average_reward_policy_iteration(&mdp, 1e-8).err().unwrap(),
+            relative_value_iteration(&mdp, 1e-8).err().unwrap(),
+        ] {
+            assert!(matches!(error.reason, StopReason::Failed(_)));
+        }
+    }
+
+    #[test]
+    fn diverging_gains_are_multichain() {
+        // two absorbing states earning different amounts per step
+        struct Split;
+        impl MDP for Split {
+            type State = u8;
+            type Action = ();
+            fn get_states(&self) -> &[u8] {
+                &[0, 1]
+            }
+            fn get_actions(&self) -> &[()] {
+                &[()]
+            }
+            fn transition(&self, state: u8, _action: ()) -> &[(u8, Probability)] {
+                if state == 0 {
+                    &[(0, 1.0)]
+                } else {
+                    &[(1, 1.0)]
+                }
+            }
+            fn reward(&self, state: u8, _action: (), _next_state: u8) -> Reward {
+                state as Reward
+            }
+        }
+
+        let criteria = StoppingCriteria::residual(1e-8).with_max_iterations(100);
+        let error = relative_value_iteration(&Split, criteria).err().unwrap();
+        assert!(
+            matches!(&error.reason, StopReason::Failed(message) if message.contains("multichain"))
+        );
+        // the states drift apart by one per step, τ of which shows each sweep
+        assert_eq!(error.last_iterate.num_iterations, 100);
+        assert!((error.last_iterate.bias[&1] - 100.0 * APERIODICITY_WEIGHT).abs() < 1e-9);
+    }
+
+    #[test]
+    fn running_out_of_budget() {
+        let criteria = StoppingCriteria::residual(1e-8).with_max_iterations(2);
+        let error = relative_value_iteration(&Continuing::new(), criteria)
+            .err()
+            .unwrap();
+        assert_eq!(error.reason, StopReason::MaxIterations(2));
+        assert_eq!(error.num_iterations, 2);
+        let last_iterate = error.last_iterate;
+        assert_eq!(last_iterate.num_iterations, 2);
+        assert_eq!(last_iterate.bias[&0], 0.0);
+        assert!(last_iterate.gain > 1.0 && last_iterate.gain < 2.0);
+
+        // the greedy start only stays put, which takes one step to improve on
+        let criteria = StoppingCriteria::residual(1e-8).with_max_iterations(1);
+        let error = average_reward_policy_iteration(&Continuing::new(), criteria)
+            .err()
+            .unwrap();
+        assert_eq!(error.reason, StopReason::MaxIterations(1));
+        assert_eq!(error.last_iterate.gain, 1.0);
+        assert_eq!(error.last_iterate.policy.get_action(&0), STAY);
+    }
+}
@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+
+use crate::{
+    environment::Reward,
+    linear_programming::OccupancyProgram,
+    mdp::{Probability, MDP},
+    occupancy,
+    policy::{Policy, StochasticMDPPolicy, StochasticPolicy},
+    policy_iteration::{evaluate_policy_exact, value_iteration},
+};
+
+/// Tolerance of the value iteration that finds each best response in
+/// [`primal_dual`]
+const BEST_RESPONSE_THRESHOLD: f64 = 1e-10;
+
+/// Occupancies this small are rounding error left by the simplex method
+const OCCUPANCY_TOLERANCE: f64 = 1e-12;
+
+/// An MDP whose transitions also incur costs, which a policy must keep
+/// within budgets on their expected discounted totals.
+pub trait ConstrainedMDP: MDP {
+    fn num_costs(&self) -> usize;
+
+    /// Cost number `index` of taking `action` in `state` and landing in
+    /// `next_state`, discounted like the reward
+    fn cost(
+        &self,
+        state: Self::State,
+        action: Self::Action,
+        next_state: Self::State,
+        index: usize,
+    ) -> f64;
+}
+
+/// One cost of a transition from `state` to `next_state` under `action`
+type CostFn<'a, M> =
+    Box<dyn Fn(<M as MDP>::State, <M as MDP>::Action, <M as MDP>::State) -> f64 + 'a>;
+
+/// An MDP with costs added by the caller, one closure per cost.
+pub struct WithCosts<'a, M: MDP> {
+    mdp: &'a M,
+    costs: Vec<CostFn<'a, M>>,
+}
+
+impl<'a, M: MDP> WithCosts<'a, M> {
+    pub fn new(mdp: &'a M) -> Self {
+        Self { mdp, costs: vec![] }
+    }
+
+    /// Adds a cost, numbered after the ones already added.
+    pub fn with_cost(mut self, cost: impl Fn(M::State, M::Action, M::State) -> f64 + 'a) -> Self {
+        self.costs.push(Box::new(cost));
+        self
+    }
+}
+
+impl<M: MDP> MDP for WithCosts<'_, M> {
+    type State = M::State;
+    type Action = M::Action;
+
+    fn get_states(&self) -> &[M::State] {
+        self.mdp.get_states()
+    }
+
+    fn get_actions(&self) -> &[M::Action] {
+        self.mdp.get_actions()
+    }
+
+    fn available_actions(&self, state: M::State) -> &[M::Action] {
+        self.mdp.available_actions(state)
+    }
+
+    fn transition(&self, state: M::State, action: M::Action) -> &[(M::State, Probability)] {
+        self.mdp.transition(state, action)
+    }
+
+    fn reward(&self, state: M::State, action: M::Action, next_state: M::State) -> Reward {
+        self.mdp.reward(state, action, next_state)
+    }
+
+    fn is_terminal(&self, state: M::State) -> bool {
+        self.mdp.is_terminal(state)
+    }
+
+    fn initial_distribution(&self) -> Vec<(M::State, Probability)> {
+        self.mdp.initial_distribution()
+    }
+}
+
+impl<M: MDP> ConstrainedMDP for WithCosts<'_, M> {
+    fn num_costs(&self) -> usize {
+        self.costs.len()
+    }
+
+    fn cost(&self, state: M::State, action: M::Action, next_state: M::State, index: usize) -> f64 {
+        (self.costs[index])(state, action, next_state)
+    }
+}
+
+/// A policy for a constrained MDP and how well it keeps to the budgets.
+pub struct ConstrainedSolution<M: MDP> {
+    pub policy: StochasticMDPPolicy<M>,
+    /// Expected discounted return from the MDP's initial distribution
+    pub expected_return: f64,
+    /// Expected discounted total of each cost from the initial distribution
+    pub expected_costs: Vec<f64>,
+    pub budgets: Vec<f64>,
+    /// Expected discounted number of visits to each state-action pair when
+    /// starting from the initial distribution and following the policy
+    pub occupancy: HashMap<(M::State, M::Action), f64>,
+    /// Lagrange multiplier of each budget, the return one more unit of that
+    /// budget is worth
+    pub multipliers: Vec<f64>,
+}
+
+impl<M: MDP> ConstrainedSolution<M> {
+    /// How far each expected cost is over its budget, zero where it is not.
+    pub fn violations(&self) -> Vec<f64> {
+        self.expected_costs
+            .iter()
+            .zip(&self.budgets)
+            .map(|(cost, budget)| (cost - budget).max(0.0))
+            .collect()
+    }
+
+    /// Whether every expected cost is within `tolerance` of its budget or under.
+    pub fn is_feasible(&self, tolerance: f64) -> bool {
+        self.violations()
+            .iter()
+            .all(|&violation| violation <= tolerance)
+    }
+}
+
+/// The MDP rewarding `reward_weight` r + Σᵢ `cost_weights[i]` cᵢ instead
+/// of its own reward.
+struct Scalarized<'a, M> {
+    mdp: &'a M,
+    reward_weight: f64,
+    cost_weights: Vec<f64>,
+}
+
+impl<'a, M: ConstrainedMDP> Scalarized<'a, M> {
+    /// The Lagrangian r - Σᵢ λᵢ cᵢ
+    fn lagrangian(mdp: &'a M, multipliers: &[f64]) -> Self {
+        Self {
+            mdp,
+            reward_weight: 1.0,
+            cost_weights: multipliers.iter().map(|lambda| -lambda).collect(),
+        }
+    }
+
+    /// Cost number `index` alone
+    fn cost(mdp: &'a M, index: usize) -> Self {
+        let mut cost_weights = vec![0.0; mdp.num_costs()];
+        cost_weights[index] = 1.0;
+        Self {
+            mdp,
+            reward_weight: 0.0,
+            cost_weights,
+        }
+    }
+}
+
+impl<M: ConstrainedMDP> MDP for Scalarized<'_, M> {
+    type State = M::State;
+    type Action = M::Action;
+
+    fn get_states(&self) -> &[M::State] {
+        self.mdp.get_states()
+    }
+
+    fn get_actions(&self) -> &[M::Action] {
+        self.mdp.get_actions()
+    }
+
+    fn available_actions(&self, state: M::State) -> &[M::Action] {
+        self.mdp.available_actions(state)
+    }
+
+    fn transition(&self, state: M::State, action: M::Action) -> &[(M::State, Probability)] {
+        self.mdp.transition(state, action)
+    }
+
+    fn reward(&self, state: M::State, action: M::Action, next_state: M::State) -> Reward {
+        let mut reward = 0.0;
+        if self.reward_weight != 0.0 {
+            reward += self.reward_weight * self.mdp.reward(state, action, next_state);
+        }
+        for (index, &weight) in self.cost_weights.iter().enumerate() {
+            if weight != 0.0 {
+                reward += weight * self.mdp.cost(state, action, next_state, index);
+            }
+        }
+        reward
+    }
+
+    fn is_terminal(&self, state: M::State) -> bool {
+        self.mdp.is_terminal(state)
+    }
+
+    fn initial_distribution(&self) -> Vec<(M::State, Probability)> {
+        self.mdp.initial_distribution()
+    }
+}
+
+/// The expected discounted value of `mdp`'s reward under `policy` from the
+/// initial distribution.
+fn expected_value<M, P>(mdp: &M, policy: &P, discount_rate: f64) -> Result<f64, String>
+where
+    M: MDP,
+    P: StochasticPolicy<M::State, M::Action>,
+{
+    let state_values = evaluate_policy_exact(mdp, policy, discount_rate)?;
+    Ok(mdp
+        .initial_distribution()
+        .iter()
+        .map(|(state, prob)| prob * state_values[state])
+        .sum())
+}
+
+/// Evaluates the return and every cost of `policy` exactly.
+fn constrained_solution<M>(
+    mdp: &M,
+    policy: StochasticMDPPolicy<M>,
+    discount_rate: f64,
+    budgets: &[f64],
+    occupancy: HashMap<(M::State, M::Action), f64>,
+    multipliers: Vec<f64>,
+) -> Result<ConstrainedSolution<M>, String>
+where
+    M: ConstrainedMDP,
+{
+    let expected_return = expected_value(mdp, &policy, discount_rate)?;
+    let expected_costs = (0..mdp.num_costs())
+        .map(|index| expected_value(&Scalarized::cost(mdp, index), &policy, discount_rate))
+        .collect::<Result<_, _>>()?;
+    Ok(ConstrainedSolution {
+        policy,
+        expected_return,
+        expected_costs,
+        budgets: budgets.to_vec(),
+        occupancy,
+        multipliers,
+    })
+}
+
+/// The stationary policy with state-action occupancy proportional to
+/// `occupancy`, π(a | s) ∝ x(s, a), ignoring negligible occupancies. States
+/// the occupancy never visits get every available action with equal
+/// probability.
+fn occupancy_policy<M: MDP>(
+    mdp: &M,
+    occupancy: &HashMap<(M::State, M::Action), f64>,
+) -> StochasticMDPPolicy<M> {
+    let action_probs = mdp
+        .get_states()
+        .iter()
+        .map(|&state| {
+            if mdp.is_terminal(state) {
+                return (state, vec![]);
+            }
+            let actions = mdp.available_actions(state);
+            let weights: Vec<f64> = actions
+                .iter()
+                .map(|action| match occupancy.get(&(state, *action)) {
+                    Some(&x) if x > OCCUPANCY_TOLERANCE => x,
+                    _ => 0.0,
+                })
+                .collect();
+            let total: f64 = weights.iter().sum();
+            let probs = if total > 0.0 {
+                actions
+                    .iter()
+                    .zip(weights)
+                    .filter(|&(_, weight)| weight > 0.0)
+                    .map(|(&action, weight)| (action, weight / total))
+                    .collect()
+            } else {
+                let prob = 1.0 / actions.len() as f64;
+                actions.iter().map(|&action| (action, prob)).collect()
+            };
+            (state, probs)
+        })
+        .collect();
+    StochasticMDPPolicy::new(action_probs)
+}
+
+fn check_budgets<M: ConstrainedMDP>(mdp: &M, budgets: &[f64]) -> Result<(), String> {
+    if budgets.len() != mdp.num_costs() {
+        return Err(format!(
+            "expected a budget for each of the {} costs, got {}",
+            mdp.num_costs(),
+            budgets.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Solves the constrained MDP as the linear program over discounted
+/// state-action occupancy measures x(s, a):
+///
+///   maximize Σ x(s, a) r(s, a)
+///   subject to Σ_a x(s', a) - γ Σ_{s,a} P(s' | s, a) x(s, a) = μ(s'),
+///              Σ x(s, a) cᵢ(s, a) ≤ bᵢ for every cost i,  x ≥ 0
+///
+/// where μ is the initial distribution: the program of
+/// [`linear_programming::solve`](crate::linear_programming::solve) with the
+/// budgets on top. Unlike that unconstrained program, the optimum may need
+/// to randomize, in at most one state per binding budget. The multipliers
+/// are the duals of the budget constraints. Fails if no policy keeps to
+/// the budgets.
+pub fn solve_lp<M>(
+    mdp: &M,
+    discount_rate: f64,
+    budgets: &[f64],
+) -> Result<ConstrainedSolution<M>, String>
+where
+    M: ConstrainedMDP,
+{
+    check_budgets(mdp, budgets)?;
+    let mut program = OccupancyProgram::new(mdp, discount_rate, &mdp.initial_distribution())?;
+    for (index, &budget) in budgets.iter().enumerate() {
+        let costs = program.expected(mdp, |state, action, next_state| {
+            mdp.cost(state, action, next_state, index)
+        });
+        program.add_budget(costs, budget);
+    }
+
+    let solution = program.maximize()?;
+
+    let multipliers = solution
+        .duals
+        .iter()
+        .skip(program.states.len())
+        .copied()
+        .collect();
+    let occupancy = program.state_actions.into_iter().zip(solution.x).collect();
+    let policy = occupancy_policy(mdp, &occupancy);
+    constrained_solution(mdp, policy, discount_rate, budgets, occupancy, multipliers)
+}
+
+/// Solves the constrained MDP by Lagrangian relaxation. Each iteration finds
+/// the best deterministic policy for the reward r - Σᵢ λᵢ cᵢ with value
+/// iteration, then moves every multiplier λᵢ by `step_size` / √k times how
+/// far that policy's expected cost is over budget, keeping it non-negative.
+///
+/// The best responses alone may jump between policies that break the
+/// budget and policies that waste it, so the returned policy averages their
+/// discounted state-action occupancies instead, which also averages their
+/// returns and costs. That average approaches the optimum as the number of
+/// iterations grows, but may still be slightly over budget.
+pub fn primal_dual<M>(
+    mdp: &M,
+    discount_rate: f64,
+    budgets: &[f64],
+    step_size: f64,
+    num_iterations: usize,
+) -> Result<ConstrainedSolution<M>, String>
+where
+    M: ConstrainedMDP,
+{
+    check_budgets(mdp, budgets)?;
+    let mut multipliers = vec![0.0; mdp.num_costs()];
+    let mut occupancy: HashMap<(M::State, M::Action), f64> = HashMap::new();
+
+    for k in 1..=num_iterations {
+        let lagrangian = Scalarized::lagrangian(mdp, &multipliers);
+        let policy = value_iteration(&lagrangian, discount_rate, BEST_RESPONSE_THRESHOLD)
+            .map_err(|e| e.to_string())?
+            .policy;
+
+        let visits = occupancy::discounted_occupancy(mdp, &policy, discount_rate)?;
+        for (&state, &visit) in &visits {
+            if !mdp.is_terminal(state) && !mdp.available_actions(state).is_empty() {
+                *occupancy
+                    .entry((state, policy.get_action(&state)))
+                    .or_insert(0.0) += visit;
+            }
+        }
+
+        let step = step_size / (k as f64).sqrt();
+        for (index, lambda) in multipliers.iter_mut().enumerate() {
+            let cost = expected_value(&Scalarized::cost(mdp, index), &policy, discount_rate)?;
+            *lambda = (*lambda + step * (cost - budgets[index])).max(0.0);
+        }
+    }
+
+    // discounted_occupancy normalizes each best response's visits to sum to one
+    let scale = (1.0 - discount_rate) * num_iterations as f64;
+    for visits in occupancy.values_mut() {
+        *visits /= scale;
+    }
+    let policy = occupancy_policy(mdp, &occupancy);
+    constrained_solution(mdp, policy, discount_rate, budgets, occupancy, multipliers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid_world::{GridWorld, GridWorldMDP, FROZEN_LAKE_4X4};
+
+    #[test]
+    fn budget_on_steps_next_to_holes() {
+        let grid_mdp =
+            GridWorldMDP::new(GridWorld::from_map(&FROZEN_LAKE_4X4, 2.0 / 3.0, 0.9).unwrap());
+        let grid_world = &grid_mdp.grid_world;
+        let mdp = WithCosts::new(&grid_mdp).with_cost(|state, _, _| {
+            if grid_world.is_next_to_hole(state) {
+                1.0
+            } else {
+                0.0
+            }
+        });
+        let unconstrained = value_iteration(&mdp, 0.9, 1e-10).unwrap();
+
+        // a budget far above what the optimal policy spends changes nothing
+        let loose = solve_lp(&mdp, 0.9, &[100.0]).unwrap();
+        assert!((loose.expected_return - unconstrained.expected_return).abs() < 1e-8);
+        assert!(loose.multipliers[0].abs() < 1e-9);
+
+        let budget = loose.expected_costs[0] / 2.0;
+        let lp = solve_lp(&mdp, 0.9, &[budget]).unwrap();
+        assert!(lp.is_feasible(1e-8));
+        assert!((lp.expected_costs[0] - budget).abs() < 1e-8);
+        assert!(lp.expected_return < loose.expected_return);
+        assert!(lp.multipliers[0] > 0.0);
+
+        // the optimum randomizes in a single state, for the one budget
+        let randomized = mdp
+            .get_states()
+            .iter()
+            .filter(|state| {
+                let visited = mdp.get_actions().iter().any(|&action| {
+                    lp.occupancy
+                        .get(&(**state, action))
+                        .is_some_and(|&x| x > 0.0)
+                });
+                visited && lp.policy.action_probs(state).len() > 1
+            })
+            .count();
+        assert_eq!(randomized, 1);
+
+        let primal_dual = primal_dual(&mdp, 0.9, &[budget], 0.01, 500).unwrap();
+        assert!(primal_dual.is_feasible(0.01 * budget));
+        assert!(
+            (primal_dual.expected_return - lp.expected_return).abs() < 0.01 * lp.expected_return
+        );
+        assert!((primal_dual.multipliers[0] - lp.multipliers[0]).abs() < 0.1 * lp.multipliers[0]);
+        // the averaged occupancy is the one the mixed policy induces
+        let reward = |state: usize, action| -> f64 {
+            mdp.transition(state, action)
+                .iter()
+                .map(|&(next_state, prob)| prob * mdp.reward(state, action, next_state))
+                .sum()
+        };
+        let occupancy_return: f64 = primal_dual
+            .occupancy
+            .iter()
+            .map(|(&(state, action), x)| x * reward(state, action))
+            .sum();
+        assert!((occupancy_return - primal_dual.expected_return).abs() < 1e-8);
+
+        // no policy can avoid the cells next to holes entirely
+        assert!(solve_lp(&mdp, 0.9, &[0.0]).is_err());
+        assert!(solve_lp(&mdp, 0.9, &[1.0, 1.0]).is_err());
+    }
+}
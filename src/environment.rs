@@ -8,6 +8,9 @@ pub trait Environment {
     type Action: Clone + Hash + Debug + Display;
 
     fn current_state(&self) -> &Self::State;
+    /// The actions that are legal in the current state
+    fn available_actions(&self) -> Vec<Self::Action>;
+    /// Takes `action`, failing if it is not available in the current state
     fn step(&mut self, action: &Self::Action) -> Result<StepResult<Self::State>, String>;
     fn reset(&mut self) -> &Self::State;
 
@@ -16,6 +19,12 @@ pub trait Environment {
     }
 }
 
+/// An environment whose randomness can be reset from a seed, so that
+/// episodes can be replayed exactly.
+pub trait SeedableEnvironment: Environment {
+    fn seed(&mut self, seed: u64);
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct StepResult<State> {
     pub state: State,
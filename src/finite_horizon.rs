@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use crate::{mdp::MDP, policy::TimeIndexedPolicy, solution::ActionValues, tabular::TabularMDP};
+
+/// Optimal behaviour when an episode is cut off after a fixed number of steps.
+pub struct FiniteHorizonSolution<M: MDP> {
+    pub policy: TimeIndexedPolicy<M>,
+    /// `state_values[t]` is the optimal expected return from timestep `t`,
+    /// with `horizon - t` steps remaining. The last entry is all zeros.
+    pub state_values: Vec<HashMap<M::State, f64>>,
+    /// `action_values[t]` is Q at timestep `t`, for `t < horizon`
+    pub action_values: Vec<ActionValues<M::State, M::Action>>,
+    /// Optimal expected return over the whole horizon from the initial
+    /// state distribution
+    pub expected_return: f64,
+}
+
+impl<M: MDP> FiniteHorizonSolution<M> {
+    pub fn horizon(&self) -> usize {
+        self.action_values.len()
+    }
+
+    pub fn state_value(&self, timestep: usize, state: &M::State) -> f64 {
+        self.state_values[timestep][state]
+    }
+}
+
+/// Solves the MDP over `horizon` steps by dynamic programming backwards from
+/// the final step, where nothing more can be earned.
+pub fn backward_induction<M>(
+    mdp: &M,
+    horizon: usize,
+    discount_rate: f64,
+) -> FiniteHorizonSolution<M>
+where
+    M: MDP,
+{
+    assert!(horizon > 0, "horizon must be at least one step");
+
+    let tabular = TabularMDP::from_mdp(mdp);
+    let mut state_values = Vec::with_capacity(horizon + 1);
+    let mut action_values = Vec::with_capacity(horizon);
+    let mut state_actions = Vec::with_capacity(horizon);
+
+    let mut values = vec![0.0; tabular.num_states()];
+    state_values.push(tabular.to_state_values(&values));
+    for _ in 0..horizon {
+        let q = tabular.q_values(&values, discount_rate);
+        let (rows, max_values) = tabular.greedy(&q);
+        values = max_values;
+        state_values.push(tabular.to_state_values(&values));
+        state_actions.push(tabular.to_actions(&rows));
+        action_values.push(tabular.to_action_values(&q));
+    }
+    state_values.reverse();
+    state_actions.reverse();
+    action_values.reverse();
+
+    FiniteHorizonSolution {
+        policy: TimeIndexedPolicy::new(state_actions),
+        state_values,
+        action_values,
+        expected_return: tabular.expected_return(&values),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generate_finite_horizon_episode,
+        grid_world::{GridWorld, GridWorldEnv, GridWorldMDP, FROZEN_LAKE_4X4},
+        policy::NonStationaryPolicy,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn goal_needs_enough_steps() {
+        // the goal is six steps from the start
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 0.0, 1.0).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        assert_eq!(backward_induction(&mdp, 6, 1.0).state_value(0, &0), 1.0);
+        assert_eq!(backward_induction(&mdp, 5, 1.0).state_value(0, &0), 0.0);
+
+        let solution = backward_induction(&mdp, 8, 1.0);
+        assert_eq!(solution.horizon(), 8);
+        assert_eq!(solution.expected_return, 1.0);
+        assert_eq!(solution.state_value(2, &0), 1.0);
+        assert_eq!(solution.state_value(3, &0), 0.0);
+        assert!(solution.state_values[8].values().all(|&v| v == 0.0));
+
+        let mut env = GridWorldEnv::new(mdp, StdRng::seed_from_u64(0));
+        assert_eq!(
+            generate_finite_horizon_episode(&mut env, &solution.policy, 8),
+            1.0
+        );
+    }
+
+    #[test]
+    fn policy_depends_on_steps_remaining() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 2.0 / 3.0, 1.0).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let solution = backward_induction(&mdp, 20, 1.0);
+
+        let changes = mdp.get_states().iter().any(|state| {
+            solution.policy.get_action_at(0, state) != solution.policy.get_action_at(19, state)
+        });
+        assert!(changes);
+
+        // more steps can only help
+        for t in 0..20 {
+            for state in mdp.get_states() {
+                assert!(solution.state_value(t, state) >= solution.state_value(t + 1, state));
+            }
+        }
+    }
+}
@@ -1,12 +1,16 @@
-use crate::environment::{Environment, Reward, StepResult};
+use crate::analysis;
+use crate::environment::{Environment, Reward, SeedableEnvironment, StepResult};
 use crate::mdp::Probability;
 use crate::policy::Policy;
+use crate::pomdp::POMDP;
+use crate::solution::OptimalActions;
 use crate::{direction::Direction, mdp::MDP};
 use itertools::Itertools;
 use rand::distributions::WeightedIndex;
 use rand::prelude::Distribution;
-use rand::rngs::ThreadRng;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use std::cmp::min;
 use std::collections::HashMap;
 use std::fmt::Write;
@@ -100,7 +104,7 @@ impl GridWorld {
 
         let mut starting_states = Vec::new();
         let grid: Vec<Cell> = map
-            .into_iter()
+            .iter()
             .flat_map(|row| row.chars())
             .enumerate()
             .map(|(i, ch)| {
@@ -134,13 +138,26 @@ impl GridWorld {
         let mut col = position % self.n_cols;
         (row, col) = match action {
             Direction::Up => (row.saturating_sub(1), col),
-            Direction::Down => (min(row + 1, self.n_cols - 1), col),
+            Direction::Down => (min(row + 1, self.n_rows - 1), col),
             Direction::Left => (row, col.saturating_sub(1)),
-            Direction::Right => (row, min(col + 1, self.n_rows - 1)),
+            Direction::Right => (row, min(col + 1, self.n_cols - 1)),
         };
         row * self.n_cols + col
     }
 
+    /// Whether the cell at `position` ends the episode without reward
+    pub fn is_hole(&self, position: usize) -> bool {
+        let cell = self.grid[position];
+        cell.is_terminal && cell.reward <= 0.0
+    }
+
+    /// Whether a single step in some direction falls into a hole
+    pub fn is_next_to_hole(&self, position: usize) -> bool {
+        DIRECTIONS
+            .iter()
+            .any(|&direction| self.is_hole(self.next_position(position, direction)))
+    }
+
     pub fn direction_probs(&self) -> HashMap<Direction, Vec<(Direction, Probability)>> {
         Direction::all()
             .into_iter()
@@ -162,14 +179,18 @@ impl GridWorld {
             .collect()
     }
 
+    /// Draws the policy's action in every cell, with terminal cells as `■`.
     pub fn render_policy(&self, policy: &impl Policy<usize, Direction>) -> String {
         {
             let mut s = String::new();
             for row in 0..self.n_rows {
                 for col in 0..self.n_cols {
                     let index = row * self.n_cols + col;
-                    let action = policy.get_action(&index);
-                    write!(s, "{} ", action).unwrap();
+                    if self.grid[index].is_terminal {
+                        write!(s, "■ ").unwrap();
+                    } else {
+                        write!(s, "{} ", policy.get_action(&index)).unwrap();
+                    }
                 }
                 writeln!(s).unwrap();
             }
@@ -177,11 +198,78 @@ impl GridWorld {
             s
         }
     }
+
+    /// Shades each cell by its share of `values`, e.g. a state-occupancy
+    /// distribution, from blank for zero to `██` for the largest value.
+    pub fn render_heatmap(&self, values: &HashMap<usize, f64>) -> String {
+        const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+        let max = values.values().copied().fold(0.0, f64::max);
+
+        let mut s = String::new();
+        for row in 0..self.n_rows {
+            for col in 0..self.n_cols {
+                let value = values
+                    .get(&(row * self.n_cols + col))
+                    .copied()
+                    .unwrap_or(0.0);
+                let level = if max > 0.0 {
+                    ((value / max).clamp(0.0, 1.0) * (SHADES.len() - 1) as f64).round() as usize
+                } else {
+                    0
+                };
+                write!(s, "{0}{0}", SHADES[level]).unwrap();
+            }
+            writeln!(s).unwrap();
+        }
+        writeln!(s).unwrap();
+        s
+    }
+
+    /// Draws every optimal action in each cell, e.g. from
+    /// [`Solution::optimal_actions`](crate::solution::Solution::optimal_actions),
+    /// so that ties show up as several arrows. Cells are padded to the same
+    /// width; terminal cells are `■` and cells without actions are `·`.
+    pub fn render_optimal_actions(
+        &self,
+        optimal_actions: &HashMap<usize, OptimalActions<Direction>>,
+    ) -> String {
+        let cells: Vec<String> = (0..self.grid.len())
+            .map(|index| {
+                if self.grid[index].is_terminal {
+                    return "■".to_string();
+                }
+                match optimal_actions.get(&index) {
+                    Some(optimal) if !optimal.actions.is_empty() => optimal
+                        .actions
+                        .iter()
+                        .map(|action| action.to_string())
+                        .collect(),
+                    _ => "·".to_string(),
+                }
+            })
+            .collect();
+        let width = cells
+            .iter()
+            .map(|cell| cell.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let mut s = String::new();
+        for row in cells.chunks(self.n_cols) {
+            for cell in row {
+                write!(s, "{:width$} ", cell, width = width).unwrap();
+            }
+            writeln!(s).unwrap();
+        }
+        writeln!(s).unwrap();
+        s
+    }
 }
 
 pub struct GridWorldMDP {
     states: Vec<usize>,
-    transitions: HashMap<(usize, Direction), Vec<(usize, Probability)>>,
+    /// indexed by `state * DIRECTIONS.len() + action as usize`
+    transitions: Vec<Vec<(usize, Probability)>>,
     rewards: Vec<Reward>,
     pub grid_world: GridWorld,
 }
@@ -189,29 +277,32 @@ pub struct GridWorldMDP {
 impl GridWorldMDP {
     pub fn new(grid_world: GridWorld) -> Self {
         let states: Vec<usize> = (0..grid_world.grid.len()).collect();
-        let actions = Direction::all();
         let rewards = grid_world.grid.iter().map(|cell| cell.reward).collect();
 
         let direction_probs = grid_world.direction_probs();
 
         let transitions = states
             .iter()
-            .cartesian_product(actions.iter())
+            .cartesian_product(DIRECTIONS.iter())
             .map(|(&state, &action)| {
                 let cell = grid_world.grid[state];
 
                 let transitions = if !cell.is_terminal {
-                    let mut next_state_probs = HashMap::new();
+                    // merged in a fixed order so seeded sampling is reproducible
+                    let mut next_state_probs: Vec<(usize, Probability)> = vec![];
                     for &(noisy_action, prob) in direction_probs[&action].iter() {
                         let next_state = grid_world.next_position(state, noisy_action);
-                        *next_state_probs.entry(next_state).or_insert(0.0) += prob;
+                        match next_state_probs.iter_mut().find(|(s, _)| *s == next_state) {
+                            Some((_, p)) => *p += prob,
+                            None => next_state_probs.push((next_state, prob)),
+                        }
                     }
-                    next_state_probs.into_iter().collect()
+                    next_state_probs
                 } else {
                     vec![]
                 };
 
-                ((state, action), transitions)
+                transitions
             })
             .collect();
 
@@ -222,6 +313,21 @@ impl GridWorldMDP {
             grid_world,
         }
     }
+
+    /// Warns when no goal cell can be reached from the start, in which case
+    /// every policy is worth nothing.
+    pub fn check_goal_reachable(&self) -> Result<(), String> {
+        let reachable = analysis::reachable_states(self);
+        let is_goal = |&state: &usize| {
+            let cell = self.grid_world.grid[state];
+            cell.is_terminal && cell.reward > 0.0
+        };
+        if reachable.iter().any(is_goal) {
+            Ok(())
+        } else {
+            Err("no goal can be reached from the start".into())
+        }
+    }
 }
 
 impl MDP for GridWorldMDP {
@@ -236,12 +342,21 @@ impl MDP for GridWorldMDP {
         &DIRECTIONS
     }
 
+    /// Every direction, except in terminal cells where there are none
+    fn available_actions(&self, state: Self::State) -> &[Self::Action] {
+        if self.is_terminal(state) {
+            &[]
+        } else {
+            &DIRECTIONS
+        }
+    }
+
     fn transition(
         &self,
         state: Self::State,
         action: Self::Action,
     ) -> &[(Self::State, Probability)] {
-        &self.transitions[&(state, action)]
+        &self.transitions[state * DIRECTIONS.len() + action as usize]
     }
 
     fn reward(
@@ -252,28 +367,150 @@ impl MDP for GridWorldMDP {
     ) -> Reward {
         self.rewards[next_state]
     }
+
+    fn is_terminal(&self, state: Self::State) -> bool {
+        self.grid_world.grid[state].is_terminal
+    }
+
+    /// Uniform over the starting states of the grid world
+    fn initial_distribution(&self) -> Vec<(Self::State, Probability)> {
+        let starting_states = &self.grid_world.starting_states;
+        let prob = 1.0 / starting_states.len() as f64;
+        starting_states.iter().map(|&state| (state, prob)).collect()
+    }
+}
+
+/// What an agent in a partially observable grid world senses of the four
+/// neighbouring cells: bit `i` of each mask is set for `Direction` `i` in
+/// up, down, left, right order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Surroundings {
+    pub walls: u8,
+    pub holes: u8,
+}
+
+impl Surroundings {
+    pub fn is_wall(&self, direction: Direction) -> bool {
+        self.walls & (1 << direction as u8) != 0
+    }
+
+    pub fn is_hole(&self, direction: Direction) -> bool {
+        self.holes & (1 << direction as u8) != 0
+    }
+}
+
+/// A grid world where the agent does not know which cell it is in. After
+/// every move it only senses which neighbouring cells are walls or holes.
+pub struct GridWorldPOMDP {
+    pub mdp: GridWorldMDP,
+    /// indexed by state
+    observations: Vec<[(Surroundings, Probability); 1]>,
+    distinct_observations: Vec<Surroundings>,
+}
+
+impl GridWorldPOMDP {
+    pub fn new(grid_world: GridWorld) -> Self {
+        let observations: Vec<[(Surroundings, Probability); 1]> = (0..grid_world.grid.len())
+            .map(|state| {
+                let mut surroundings = Surroundings { walls: 0, holes: 0 };
+                for direction in DIRECTIONS {
+                    let neighbour = grid_world.next_position(state, direction);
+                    if neighbour == state {
+                        surroundings.walls |= 1 << direction as u8;
+                    } else if grid_world.is_hole(neighbour) {
+                        surroundings.holes |= 1 << direction as u8;
+                    }
+                }
+                [(surroundings, 1.0)]
+            })
+            .collect();
+        let distinct_observations = observations
+            .iter()
+            .map(|&[(surroundings, _)]| surroundings)
+            .unique()
+            .collect();
+        Self {
+            mdp: GridWorldMDP::new(grid_world),
+            observations,
+            distinct_observations,
+        }
+    }
+}
+
+impl MDP for GridWorldPOMDP {
+    type State = usize;
+    type Action = Direction;
+
+    fn get_states(&self) -> &[Self::State] {
+        self.mdp.get_states()
+    }
+
+    fn get_actions(&self) -> &[Self::Action] {
+        self.mdp.get_actions()
+    }
+
+    fn available_actions(&self, state: Self::State) -> &[Self::Action] {
+        self.mdp.available_actions(state)
+    }
+
+    fn transition(
+        &self,
+        state: Self::State,
+        action: Self::Action,
+    ) -> &[(Self::State, Probability)] {
+        self.mdp.transition(state, action)
+    }
+
+    fn reward(&self, state: Self::State, action: Self::Action, next_state: Self::State) -> Reward {
+        self.mdp.reward(state, action, next_state)
+    }
+
+    fn is_terminal(&self, state: Self::State) -> bool {
+        self.mdp.is_terminal(state)
+    }
+
+    fn initial_distribution(&self) -> Vec<(Self::State, Probability)> {
+        self.mdp.initial_distribution()
+    }
+}
+
+impl POMDP for GridWorldPOMDP {
+    type Observation = Surroundings;
+
+    fn get_observations(&self) -> &[Self::Observation] {
+        &self.distinct_observations
+    }
+
+    /// The surroundings of `next_state`, sensed without noise
+    fn observation(
+        &self,
+        _action: Self::Action,
+        next_state: Self::State,
+    ) -> &[(Self::Observation, Probability)] {
+        &self.observations[next_state]
+    }
 }
 
 // TODO: make mdp a reference so many environments can refer to the same MDP
-pub struct GridWorldEnv {
+pub struct GridWorldEnv<R: Rng = StdRng> {
     state: usize,
     pub mdp: GridWorldMDP,
-    rng: ThreadRng,
+    rng: R,
 }
 
-impl GridWorldEnv {
-    pub fn new(mdp: GridWorldMDP, mut rng: ThreadRng) -> Self {
+impl<R: Rng> GridWorldEnv<R> {
+    pub fn new(mdp: GridWorldMDP, mut rng: R) -> Self {
         let state = mdp
             .grid_world
             .starting_states
             .choose(&mut rng)
-            .unwrap()
-            .clone();
+            .copied()
+            .unwrap();
         GridWorldEnv { state, mdp, rng }
     }
 }
 
-impl Environment for GridWorldEnv {
+impl<R: Rng> Environment for GridWorldEnv<R> {
     type State = usize;
     type Action = Direction;
 
@@ -281,16 +518,26 @@ impl Environment for GridWorldEnv {
         &self.state
     }
 
+    fn available_actions(&self) -> Vec<Self::Action> {
+        self.mdp.available_actions(self.state).to_vec()
+    }
+
     fn step(
         &mut self,
         action: &Self::Action,
     ) -> Result<crate::environment::StepResult<Self::State>, String> {
+        if !self.mdp.available_actions(self.state).contains(action) {
+            return Err(format!(
+                "{} is not available in state {}",
+                action, self.state
+            ));
+        }
         let next_state_probs = self.mdp.transition(self.state, *action);
         let (next_states, probs): (Vec<_>, Vec<_>) = next_state_probs.iter().cloned().unzip();
         let dist = WeightedIndex::new(&probs).unwrap();
         let next_state = next_states[dist.sample(&mut self.rng)];
         let reward = self.mdp.reward(self.state, *action, next_state);
-        let is_done = self.mdp.grid_world.grid[next_state].is_terminal;
+        let is_done = self.mdp.is_terminal(next_state);
 
         self.state = next_state;
 
@@ -303,12 +550,18 @@ impl Environment for GridWorldEnv {
             .grid_world
             .starting_states
             .choose(&mut self.rng)
-            .unwrap()
-            .clone();
+            .copied()
+            .unwrap();
         &self.state
     }
 }
 
+impl<R: Rng + SeedableRng> SeedableEnvironment for GridWorldEnv<R> {
+    fn seed(&mut self, seed: u64) {
+        self.rng = R::seed_from_u64(seed);
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -347,10 +600,95 @@ mod tests {
         let actions = mdp.get_actions();
         assert_eq!(actions.len(), 4);
 
+        assert!(mdp.is_terminal(5) && mdp.is_terminal(15) && !mdp.is_terminal(0));
+        assert!(mdp.available_actions(15).is_empty());
+        assert_eq!(mdp.initial_distribution(), vec![(0, 1.0)]);
+
         let total_reward: f64 = mdp.rewards.into_iter().sum();
         assert_eq!(total_reward, 1.0);
     }
 
+    #[test]
+    fn render_marks_terminal_cells() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 0.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let solution = crate::policy_iteration::value_iteration(&mdp, 0.9, 1e-8).unwrap();
+        let rendered = mdp.grid_world.render_policy(&solution.policy);
+        let rows: Vec<&str> = rendered.lines().collect();
+        assert_eq!(rows[1], "↓ ■ ↓ ■ ");
+        assert!(rows[3].ends_with("■ "));
+    }
+
+    #[test]
+    fn render_shows_tied_actions() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 0.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let solution = crate::policy_iteration::value_iteration(&mdp, 0.9, 1e-10).unwrap();
+        let optimal = solution.optimal_actions(&mdp, 1e-6);
+
+        // from the start, going down or right reaches the goal equally fast
+        assert_eq!(optimal[&0].actions, vec![Direction::Down, Direction::Right]);
+        let gap = optimal[&0].gap.unwrap();
+        assert!((gap - (0.9f64.powi(5) - 0.9f64.powi(6))).abs() < 1e-8);
+        // next to the goal the only alternative is staying put
+        assert_eq!(optimal[&14].actions, vec![Direction::Right]);
+        assert!(!optimal.contains_key(&5));
+
+        let rendered = mdp.grid_world.render_optimal_actions(&optimal);
+        let rows: Vec<&str> = rendered.lines().collect();
+        assert!(rows[0].starts_with("↓→ "));
+        assert!(rows[1].starts_with("↓  ■  "));
+    }
+
+    #[test]
+    fn unreachable_goal() {
+        let mdp = GridWorldMDP::new(GridWorld::from_map(&["SH", "HG"], 2.0 / 3.0, 0.9).unwrap());
+        assert!(mdp.check_goal_reachable().is_err());
+        let mdp = GridWorldMDP::new(GridWorld::from_map(&FROZEN_LAKE_8X8, 0.0, 0.9).unwrap());
+        assert!(mdp.check_goal_reachable().is_ok());
+    }
+
+    #[test]
+    fn sensing_walls_and_holes() {
+        let pomdp = GridWorldPOMDP::new(GridWorld::from_map(&FROZEN_LAKE_4X4, 0.0, 0.9).unwrap());
+        let start = pomdp.observation(Direction::Up, 0)[0].0;
+        assert!(start.is_wall(Direction::Up) && start.is_wall(Direction::Left));
+        assert!(!start.is_hole(Direction::Down) && !start.is_hole(Direction::Right));
+        let below_start = pomdp.observation(Direction::Down, 4)[0].0;
+        assert!(below_start.is_hole(Direction::Right) && below_start.is_wall(Direction::Left));
+
+        // moving right from the start, only cell 1 has a hole below it
+        let mut filter = crate::pomdp::BeliefFilter::new(&pomdp);
+        let observation = pomdp.observation(Direction::Right, 1)[0].0;
+        let belief = filter.update(Direction::Right, observation).unwrap();
+        assert_eq!(belief.len(), 1);
+        assert_eq!(belief[&1], 1.0);
+    }
+
+    #[test]
+    fn non_square_grid() {
+        #[rustfmt::skip]
+        let map = [
+            "SFFH",
+            "FFFG",
+        ];
+        let grid_world = GridWorld::from_map(&map, 0.0, 0.9).unwrap();
+        assert_eq!(grid_world.next_position(2, Direction::Right), 3);
+        assert_eq!(grid_world.next_position(3, Direction::Right), 3);
+        assert_eq!(grid_world.next_position(5, Direction::Down), 5);
+        assert_eq!(grid_world.next_position(1, Direction::Down), 5);
+        assert!(grid_world.is_next_to_hole(2) && grid_world.is_next_to_hole(7));
+        assert!(!grid_world.is_next_to_hole(1));
+
+        let pomdp = GridWorldPOMDP::new(grid_world);
+        let corner = pomdp.observation(Direction::Down, 4)[0].0;
+        assert!(corner.is_wall(Direction::Down) && corner.is_wall(Direction::Left));
+        assert!(!corner.is_wall(Direction::Right));
+        let beside_hole = pomdp.observation(Direction::Right, 2)[0].0;
+        assert!(beside_hole.is_hole(Direction::Right));
+        assert!(crate::validation::validate(&pomdp.mdp).is_valid());
+    }
+
     #[test]
     fn make_grid_world_8x8() {
         let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 0.0, 1.0).unwrap();
@@ -362,4 +700,27 @@ mod tests {
         let actions = mdp.get_actions();
         assert_eq!(actions.len(), 4);
     }
+
+    #[test]
+    fn seeded_env_is_reproducible() {
+        let trajectory = |seed| {
+            let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 2.0 / 3.0, 1.0).unwrap();
+            let mut env =
+                GridWorldEnv::new(GridWorldMDP::new(grid_world), StdRng::seed_from_u64(0));
+            env.seed(seed);
+            env.reset();
+            (0..50)
+                .map(|_| {
+                    let result = env.step(&Direction::Right).unwrap();
+                    if result.is_done {
+                        env.reset();
+                    }
+                    result.state
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(trajectory(7), trajectory(7));
+        assert_ne!(trajectory(7), trajectory(8));
+    }
 }
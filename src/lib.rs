@@ -1,27 +1,53 @@
-use environment::Reward;
-use policy::MDPPolicy;
+use environment::{Reward, SeedableEnvironment};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
     environment::Environment,
-    grid_world::{GridWorldEnv, GridWorldMDP},
-    policy::Policy,
+    policy::{NonStationaryPolicy, StochasticPolicy},
 };
 
+pub mod absorbing;
 pub mod agent;
+pub mod analysis;
+pub mod average_reward;
+pub mod constrained;
 pub mod direction;
 pub mod environment;
+pub mod finite_horizon;
 pub mod grid_world;
+pub mod linalg;
+pub mod linear_programming;
 pub mod mdp;
+pub mod mdp_env;
+pub mod occupancy;
+pub mod pbvi;
 pub mod policy;
 pub mod policy_iteration;
+pub mod pomdp;
+pub mod pomdp_env;
+pub mod prioritized_sweeping;
+pub mod robust;
+pub mod simplex;
+pub mod soft_value_iteration;
+pub mod solution;
+pub mod stopping;
+pub mod tabular;
+pub mod tiger;
+pub mod validation;
 
-pub fn generate_episode(env: &mut GridWorldEnv, policy: &MDPPolicy<GridWorldMDP>) -> Reward {
+/// Runs one episode to termination, sampling actions from `policy` with `rng`.
+pub fn generate_episode<E, P, R>(env: &mut E, policy: &P, rng: &mut R) -> Reward
+where
+    E: Environment,
+    P: StochasticPolicy<E::State, E::Action>,
+    R: Rng + ?Sized,
+{
     let mut is_done = false;
     let mut state = env.reset().clone();
     let mut total_reward = 0.0;
 
     while !is_done {
-        let action = policy.get_action(&state);
+        let action = policy.sample_action(&state, rng);
         let result = env.step(&action).unwrap();
         is_done = result.is_done;
         state = result.state;
@@ -30,3 +56,97 @@ pub fn generate_episode(env: &mut GridWorldEnv, policy: &MDPPolicy<GridWorldMDP>
 
     total_reward
 }
+
+/// Like [`generate_episode`], but tells the policy the current timestep and
+/// stops after at most `horizon` steps.
+pub fn generate_finite_horizon_episode<E, P>(env: &mut E, policy: &P, horizon: usize) -> Reward
+where
+    E: Environment,
+    P: NonStationaryPolicy<E::State, E::Action>,
+{
+    let mut state = env.reset().clone();
+    let mut total_reward = 0.0;
+
+    for timestep in 0..horizon {
+        let action = policy.get_action_at(timestep, &state);
+        let result = env.step(&action).unwrap();
+        state = result.state;
+        total_reward += result.reward;
+        if result.is_done {
+            break;
+        }
+    }
+
+    total_reward
+}
+
+/// Runs `num_episodes` episodes, reseeding the environment and the policy's
+/// sampling before each one with seeds drawn from `seed`, so the whole batch
+/// can be replayed exactly.
+pub fn generate_episodes<E, P>(
+    env: &mut E,
+    policy: &P,
+    num_episodes: usize,
+    seed: u64,
+) -> Vec<Reward>
+where
+    E: SeedableEnvironment,
+    P: StochasticPolicy<E::State, E::Action>,
+{
+    let mut seeds = StdRng::seed_from_u64(seed);
+    (0..num_episodes)
+        .map(|_| {
+            env.seed(seeds.gen());
+            let mut rng = StdRng::seed_from_u64(seeds.gen());
+            generate_episode(env, policy, &mut rng)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        grid_world::{GridWorld, GridWorldEnv, GridWorldMDP, FROZEN_LAKE_8X8},
+        policy::StochasticMDPPolicy,
+        policy_iteration,
+    };
+
+    #[test]
+    fn same_seed_same_episodes() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 2.0 / 3.0, 0.99).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let policy = policy_iteration::value_iteration(&mdp, 0.99, 1e-5)
+            .unwrap()
+            .policy;
+        let mut env = GridWorldEnv::new(mdp, StdRng::seed_from_u64(0));
+
+        let first = generate_episodes(&mut env, &policy, 200, 42);
+        let second = generate_episodes(&mut env, &policy, 200, 42);
+        let other = generate_episodes(&mut env, &policy, 200, 43);
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn stochastic_policy_episodes() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 0.0, 0.99).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let policy = policy_iteration::value_iteration(&mdp, 0.99, 1e-5)
+            .unwrap()
+            .policy;
+        let greedy = StochasticMDPPolicy::epsilon_greedy(&mdp, &policy, 0.0);
+        let uniform = StochasticMDPPolicy::uniform(&mdp);
+        let mut env = GridWorldEnv::new(mdp, StdRng::seed_from_u64(0));
+
+        // deterministic dynamics: only exploration can stop us reaching the goal
+        assert!(generate_episodes(&mut env, &greedy, 50, 1)
+            .iter()
+            .all(|&r| r == 1.0));
+        let random = generate_episodes(&mut env, &uniform, 500, 1);
+        let successes = random.iter().filter(|&&r| r == 1.0).count();
+        assert!(successes > 0 && successes < 100);
+        assert_eq!(random, generate_episodes(&mut env, &uniform, 500, 1));
+    }
+}
@@ -0,0 +1,102 @@
+use ndarray::{Array1, Array2};
+
+const PIVOT_TOLERANCE: f64 = 1e-12;
+
+/// LU decomposition with partial pivoting, PA = LU, for solving dense square
+/// systems. L (unit diagonal) and U are packed into a single matrix.
+pub struct LuDecomposition {
+    lu: Array2<f64>,
+    permutation: Vec<usize>,
+}
+
+impl LuDecomposition {
+    pub fn new(mut a: Array2<f64>) -> Result<Self, String> {
+        let n = a.nrows();
+        if a.ncols() != n {
+            return Err(format!("matrix is not square: {}x{}", n, a.ncols()));
+        }
+
+        let mut permutation: Vec<usize> = (0..n).collect();
+        for k in 0..n {
+            let pivot_row = (k..n)
+                .max_by(|&i, &j| a[[i, k]].abs().total_cmp(&a[[j, k]].abs()))
+                .unwrap();
+            let pivot_size = a[[pivot_row, k]].abs();
+            if pivot_size.is_nan() || pivot_size <= PIVOT_TOLERANCE {
+                return Err(format!("matrix is singular at column {}", k));
+            }
+            if pivot_row != k {
+                for j in 0..n {
+                    a.swap([k, j], [pivot_row, j]);
+                }
+                permutation.swap(k, pivot_row);
+            }
+
+            let pivot = a[[k, k]];
+            for i in (k + 1)..n {
+                let factor = a[[i, k]] / pivot;
+                a[[i, k]] = factor;
+                if factor != 0.0 {
+                    for j in (k + 1)..n {
+                        a[[i, j]] -= factor * a[[k, j]];
+                    }
+                }
+            }
+        }
+
+        Ok(Self { lu: a, permutation })
+    }
+
+    pub fn solve(&self, b: &Array1<f64>) -> Array1<f64> {
+        let n = self.lu.nrows();
+        assert_eq!(b.len(), n);
+
+        // forward substitution with L
+        let mut x: Array1<f64> = self.permutation.iter().map(|&i| b[i]).collect();
+        for i in 0..n {
+            for j in 0..i {
+                x[i] -= self.lu[[i, j]] * x[j];
+            }
+        }
+
+        // back substitution with U
+        for i in (0..n).rev() {
+            for j in (i + 1)..n {
+                x[i] -= self.lu[[i, j]] * x[j];
+            }
+            x[i] /= self.lu[[i, i]];
+        }
+
+        x
+    }
+}
+
+/// Solves Ax = b.
+pub fn solve(a: Array2<f64>, b: &Array1<f64>) -> Result<Array1<f64>, String> {
+    Ok(LuDecomposition::new(a)?.solve(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn solve_needs_pivoting() {
+        let a = array![[0.0, 2.0, 1.0], [1.0, -2.0, -3.0], [-1.0, 1.0, 2.0]];
+        let b = array![-8.0, 0.0, 3.0];
+        let x = solve(a.clone(), &b).unwrap();
+
+        let residual = a.dot(&x) - &b;
+        assert!(residual.iter().all(|r| r.abs() < 1e-12));
+        assert!((&x - &array![-4.0, -5.0, 2.0])
+            .iter()
+            .all(|e| e.abs() < 1e-12));
+    }
+
+    #[test]
+    fn singular_matrix() {
+        let a = array![[1.0, 2.0], [2.0, 4.0]];
+        assert!(LuDecomposition::new(a).is_err());
+    }
+}
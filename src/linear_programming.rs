@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use ndarray::{s, Array1, Array2};
+
+use crate::{
+    mdp::{Probability, MDP},
+    policy::MDPPolicy,
+    simplex,
+};
+
+/// Optimal solution of the discounted MDP linear program.
+pub struct LpSolution<M: MDP> {
+    pub policy: MDPPolicy<M>,
+    /// Optimal values, read off the duals of the flow constraints. Exact in
+    /// the states the optimal policy visits from the start distribution;
+    /// elsewhere only upper bounds on the optimal values.
+    pub state_values: HashMap<M::State, f64>,
+    /// Expected discounted number of visits to each state-action pair when
+    /// starting from the start distribution and acting optimally
+    pub occupancy: HashMap<(M::State, M::Action), f64>,
+    /// Optimal value of the LP, the expected discounted return from the
+    /// start distribution
+    pub objective: f64,
+    /// Expected discounted return from the MDP's initial distribution
+    pub expected_return: f64,
+    pub num_pivots: usize,
+}
+
+/// The linear program over the discounted state-action occupancy measures
+/// x(s, a) of an MDP, with one column per available action of every
+/// non-terminal state and the flow constraints
+///
+///   Σ_a x(s', a) - γ Σ_{s,a} P(s' | s, a) x(s, a) = μ(s')
+///
+/// for every non-terminal state s', where μ is the start distribution.
+/// Budgets on other occupancy-weighted totals go on top, each with a slack
+/// column of its own.
+pub(crate) struct OccupancyProgram<S, A> {
+    /// One per flow constraint
+    pub(crate) states: Vec<S>,
+    /// One per occupancy column
+    pub(crate) state_actions: Vec<(S, A)>,
+    flow: Array2<f64>,
+    rewards: Array1<f64>,
+    initial: Array1<f64>,
+    /// The amount each column adds per visit, and the bound on the total
+    budgets: Vec<(Array1<f64>, f64)>,
+}
+
+impl<S, A> OccupancyProgram<S, A>
+where
+    S: Copy + Hash + Eq,
+    A: Copy + Hash + Eq,
+{
+    /// The flow constraints of `mdp` with start distribution `initial`. Mass
+    /// on terminal states ends the episode at once and drops out. Fails if
+    /// a non-terminal state has no available action.
+    pub(crate) fn new<M>(
+        mdp: &M,
+        discount_rate: f64,
+        initial: &[(S, Probability)],
+    ) -> Result<Self, String>
+    where
+        M: MDP<State = S, Action = A>,
+    {
+        let states: Vec<S> = mdp
+            .get_states()
+            .iter()
+            .copied()
+            .filter(|&state| !mdp.is_terminal(state))
+            .collect();
+        if states
+            .iter()
+            .any(|&state| mdp.available_actions(state).is_empty())
+        {
+            return Err("every non-terminal state needs an available action".into());
+        }
+        let state_indices: HashMap<S, usize> =
+            states.iter().enumerate().map(|(i, &s)| (s, i)).collect();
+        let state_actions: Vec<(S, A)> = mdp
+            .state_actions()
+            .filter(|(s, _)| state_indices.contains_key(s))
+            .map(|(&s, &a)| (s, a))
+            .collect();
+
+        let mut flow = Array2::zeros((states.len(), state_actions.len()));
+        for (k, &(state, action)) in state_actions.iter().enumerate() {
+            flow[[state_indices[&state], k]] += 1.0;
+            for &(next_state, prob) in mdp.transition(state, action) {
+                if let Some(&j) = state_indices.get(&next_state) {
+                    flow[[j, k]] -= discount_rate * prob;
+                }
+            }
+        }
+        let mut start = Array1::zeros(states.len());
+        for &(state, prob) in initial {
+            if let Some(&i) = state_indices.get(&state) {
+                start[i] += prob;
+            }
+        }
+
+        let mut program = Self {
+            states,
+            state_actions,
+            flow,
+            rewards: Array1::zeros(0),
+            initial: start,
+            budgets: vec![],
+        };
+        program.rewards = program.expected(mdp, |state, action, next_state| {
+            mdp.reward(state, action, next_state)
+        });
+        Ok(program)
+    }
+
+    /// Σ P(s' | s, a) f(s, a, s') for every occupancy column
+    pub(crate) fn expected<M>(&self, mdp: &M, f: impl Fn(S, A, S) -> f64) -> Array1<f64>
+    where
+        M: MDP<State = S, Action = A>,
+    {
+        self.state_actions
+            .iter()
+            .map(|&(state, action)| {
+                mdp.transition(state, action)
+                    .iter()
+                    .map(|&(next_state, prob)| prob * f(state, action, next_state))
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Adds the constraint Σ x(s, a) amounts(s, a) ≤ bound.
+    pub(crate) fn add_budget(&mut self, amounts: Array1<f64>, bound: f64) {
+        self.budgets.push((amounts, bound));
+    }
+
+    /// Maximizes the expected discounted reward. The solution has the
+    /// occupancies followed by one slack per budget, and the duals of the
+    /// flow constraints in the order of `states` followed by one per budget.
+    pub(crate) fn maximize(&self) -> Result<simplex::LpSolution, String> {
+        let (n, k) = self.flow.dim();
+        let num_budgets = self.budgets.len();
+        let mut constraints = Array2::zeros((n + num_budgets, k + num_budgets));
+        constraints.slice_mut(s![..n, ..k]).assign(&self.flow);
+        let mut rewards = Array1::zeros(k + num_budgets);
+        rewards.slice_mut(s![..k]).assign(&self.rewards);
+        let mut bounds = Array1::zeros(n + num_budgets);
+        bounds.slice_mut(s![..n]).assign(&self.initial);
+        for (index, (amounts, bound)) in self.budgets.iter().enumerate() {
+            constraints.slice_mut(s![n + index, ..k]).assign(amounts);
+            constraints[[n + index, k + index]] = 1.0;
+            bounds[n + index] = *bound;
+        }
+        simplex::maximize(&rewards, &constraints, &bounds)
+    }
+}
+
+/// Solves the MDP as the linear program over discounted state-action
+/// occupancy measures x(s, a) from its initial distribution μ:
+///
+///   maximize Σ x(s, a) r(s, a)
+///   subject to Σ_a x(s', a) - γ Σ_{s,a} P(s' | s, a) x(s, a) = μ(s'),  x ≥ 0
+///
+/// Its dual is the familiar "minimize Σ μ(s) V(s) subject to V ≥ TV"
+/// program, so the optimal values come back as the dual solution. That only
+/// pins down the values of states the optimal policy visits, see
+/// [`solve_from`] for a start distribution that covers them all. Terminal
+/// states are left out and are worth nothing.
+pub fn solve<M>(mdp: &M, discount_rate: f64) -> Result<LpSolution<M>, String>
+where
+    M: MDP,
+{
+    solve_from(mdp, discount_rate, &mdp.initial_distribution())
+}
+
+/// [`solve`] with the start distribution `initial` in place of the MDP's
+/// initial distribution. With positive mass on every non-terminal state,
+/// e.g. uniform, every state's value and action is optimal.
+pub fn solve_from<M>(
+    mdp: &M,
+    discount_rate: f64,
+    initial: &[(M::State, Probability)],
+) -> Result<LpSolution<M>, String>
+where
+    M: MDP,
+{
+    let program = OccupancyProgram::new(mdp, discount_rate, initial)?;
+    let solution = program.maximize()?;
+
+    // states the optimal policy never visits keep their first action
+    let mut best: HashMap<M::State, (M::Action, f64)> = HashMap::new();
+    for (&(state, action), &x) in program.state_actions.iter().zip(solution.x.iter()) {
+        match best.get(&state) {
+            Some(&(_, best_x)) if best_x >= x => (),
+            _ => {
+                best.insert(state, (action, x));
+            }
+        }
+    }
+    let policy = MDPPolicy::new(best.into_iter().map(|(s, (a, _))| (s, a)).collect());
+
+    let mut state_values: HashMap<M::State, f64> =
+        mdp.get_states().iter().map(|&s| (s, 0.0)).collect();
+    state_values.extend(
+        program
+            .states
+            .iter()
+            .copied()
+            .zip(solution.duals.iter().copied()),
+    );
+    let expected_return = mdp
+        .initial_distribution()
+        .iter()
+        .map(|(state, prob)| prob * state_values.get(state).unwrap_or(&0.0))
+        .sum();
+
+    Ok(LpSolution {
+        policy,
+        state_values,
+        occupancy: program.state_actions.into_iter().zip(solution.x).collect(),
+        objective: solution.objective,
+        expected_return,
+        num_pivots: solution.num_pivots,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        grid_world::{GridWorld, GridWorldMDP, FROZEN_LAKE_4X4},
+        occupancy::discounted_occupancy,
+        policy::Policy,
+        policy_iteration,
+    };
+
+    #[test]
+    fn matches_value_iteration() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 2.0 / 3.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        // start anywhere so that every value is pinned down
+        let uniform: Vec<(usize, Probability)> = mdp
+            .get_states()
+            .iter()
+            .filter(|&&state| !mdp.is_terminal(state))
+            .map(|&state| (state, 1.0 / 11.0))
+            .collect();
+        let lp = solve_from(&mdp, 0.9, &uniform).unwrap();
+        let vi = policy_iteration::value_iteration(&mdp, 0.9, 1e-10).unwrap();
+
+        for state in mdp.get_states() {
+            assert!((lp.state_values[state] - vi.state_value(state)).abs() < 1e-6);
+            if mdp.is_terminal(*state) {
+                continue;
+            }
+            let action = lp.policy.get_action(state);
+            assert!((vi.action_value(state, &action) - vi.state_value(state)).abs() < 1e-6);
+        }
+
+        // strong duality: the occupancy-weighted reward equals the mean
+        // optimal value of the 11 non-terminal states
+        assert!(lp.occupancy.values().all(|&x| x >= 0.0));
+        let mean_value = vi.state_values.values().sum::<f64>() / 11.0;
+        assert!((lp.objective - mean_value).abs() < 1e-6);
+        assert!((lp.expected_return - vi.expected_return).abs() < 1e-6);
+        assert_eq!(vi.expected_return, vi.state_value(&0));
+    }
+
+    #[test]
+    fn occupancy_from_the_initial_distribution() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 2.0 / 3.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let lp = solve(&mdp, 0.9).unwrap();
+        let vi = policy_iteration::value_iteration(&mdp, 0.9, 1e-10).unwrap();
+        assert!((lp.objective - vi.expected_return).abs() < 1e-6);
+        assert!((lp.expected_return - vi.expected_return).abs() < 1e-6);
+
+        // the visits of each state match those of following the policy from S
+        let visits = discounted_occupancy(&mdp, &lp.policy, 0.9).unwrap();
+        for &state in mdp.get_states() {
+            if mdp.is_terminal(state) {
+                continue;
+            }
+            let x: f64 = mdp
+                .get_actions()
+                .iter()
+                .map(|&action| lp.occupancy[&(state, action)])
+                .sum();
+            assert!((x * (1.0 - 0.9) - visits[&state]).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn occupancy_of_a_continuing_task() {
+        // no cell ends the episode, so all 1/(1 - γ) of the discounted visits
+        // from the start stay among the four states
+        let grid_world = GridWorld::from_map(&["SF", "FF"], 0.5, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let lp = solve(&mdp, 0.9).unwrap();
+        let total: f64 = lp.occupancy.values().sum();
+        assert!((total - 1.0 / (1.0 - 0.9)).abs() < 1e-9);
+        // the start is visited at least once
+        let start: f64 = mdp
+            .get_actions()
+            .iter()
+            .map(|&action| lp.occupancy[&(0, action)])
+            .sum();
+        assert!(start >= 1.0 - 1e-9);
+    }
+}
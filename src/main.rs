@@ -1,36 +1,108 @@
 use inf_rl::{
-    generate_episode,
+    absorbing::absorbing_chain,
+    constrained::{self, WithCosts},
+    generate_episodes,
     grid_world::{GridWorld, GridWorldEnv, GridWorldMDP, FROZEN_LAKE_4X4, FROZEN_LAKE_8X8},
-    policy_iteration,
+    policy_iteration, prioritized_sweeping,
+    robust::{evaluate_policy_robust, robust_value_iteration, UncertaintySet},
 };
 use ndarray::Array;
+use rand::{rngs::StdRng, SeedableRng};
 
 fn main() -> Result<(), String> {
     let discount_factor = 0.99;
     let threshold = 1e-5;
+    let seed = 0;
 
-    let mut rng = rand::thread_rng();
+    let mut rng = StdRng::seed_from_u64(seed);
     let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 0.0, discount_factor).unwrap();
     let mdp = GridWorldMDP::new(grid_world);
-    let policy = policy_iteration::policy_iteration(&mdp, discount_factor, threshold, &mut rng);
+    let solution = policy_iteration::policy_iteration(&mdp, discount_factor, threshold, &mut rng)
+        .map_err(|e| e.to_string())?;
+    println!("num iterations: {}", solution.num_iterations);
+    let policy = solution.policy;
+    let chain = absorbing_chain(&mdp, &policy)?;
     let mut env = GridWorldEnv::new(mdp, rng);
     let num_episodes = 10000;
-    let rewards = Array::from_iter((0..num_episodes).map(|_| generate_episode(&mut env, &policy)));
+    let rewards = Array::from(generate_episodes(&mut env, &policy, num_episodes, seed));
     let mean_reward = rewards.mean().unwrap();
     println!("Policy Iteration: {}", mean_reward);
+    println!(
+        "exact success rate: {}, mean episode length: {}",
+        chain.probability_of(&[15]),
+        chain.expected_length
+    );
 
-    let rng = rand::thread_rng();
+    let rng = StdRng::seed_from_u64(seed);
     let noise = 2.0 / 3.0;
     let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, noise, discount_factor).unwrap();
     let mdp = GridWorldMDP::new(grid_world);
-    let policy = policy_iteration::value_iteration(&mdp, mdp.grid_world.discount_factor, threshold);
-    mdp.grid_world.render_policy(&policy);
+    if let Err(warning) = mdp.check_goal_reachable() {
+        eprintln!("warning: {}", warning);
+    }
+    let solution =
+        policy_iteration::value_iteration(&mdp, mdp.grid_world.discount_factor, threshold)
+            .map_err(|e| e.to_string())?;
+    println!("num iterations: {}", solution.num_iterations);
+    println!("expected return: {}", solution.expected_return);
+    let prioritized =
+        prioritized_sweeping::prioritized_sweeping(&mdp, mdp.grid_world.discount_factor, threshold)
+            .map_err(|e| e.to_string())?;
+    println!(
+        "num backups: {} (value iteration), {} (prioritized sweeping)",
+        solution.num_backups, prioritized.num_backups
+    );
+    let policy = solution.policy;
+    print!("{}", mdp.grid_world.render_policy(&policy));
+    let chain = absorbing_chain(&mdp, &policy)?;
+
+    // the slip probability is only an estimate
+    let uncertainty = UncertaintySet::L1Ball { radius: 0.2 };
+    let robust = robust_value_iteration(&mdp, discount_factor, uncertainty, threshold)
+        .map_err(|e| e.to_string())?;
+    let worst_case = |policy| {
+        evaluate_policy_robust(&mdp, policy, discount_factor, uncertainty, 1e-10)
+            .map(|(values, _)| values[&0])
+            .map_err(|e| e.to_string())
+    };
+    println!(
+        "worst-case return: {} (nominal policy), {} (robust policy)",
+        worst_case(&policy)?,
+        worst_case(&robust.policy)?
+    );
+
+    // limit the expected discounted number of steps taken next to a hole
+    {
+        let near_holes = WithCosts::new(&mdp).with_cost(|state, _, _| {
+            if mdp.grid_world.is_next_to_hole(state) {
+                1.0
+            } else {
+                0.0
+            }
+        });
+        let budget = [5.0];
+        let lp = constrained::solve_lp(&near_holes, discount_factor, &budget)?;
+        let primal_dual =
+            constrained::primal_dual(&near_holes, discount_factor, &budget, 0.01, 200)?;
+        println!(
+            "constrained return: {} (LP), {} (primal-dual), steps next to holes: {:?}, {:?}",
+            lp.expected_return,
+            primal_dual.expected_return,
+            lp.expected_costs,
+            primal_dual.expected_costs
+        );
+    }
 
     let mut env = GridWorldEnv::new(mdp, rng);
     let num_episodes = 10000;
-    let rewards = Array::from_iter((0..num_episodes).map(|_| generate_episode(&mut env, &policy)));
+    let rewards = Array::from(generate_episodes(&mut env, &policy, num_episodes, seed));
     let mean_reward = rewards.mean().unwrap();
     println!("Value Iteration: {}", mean_reward);
+    println!(
+        "exact success rate: {}, mean episode length: {}",
+        chain.probability_of(&[63]),
+        chain.expected_length
+    );
 
     Ok(())
 }
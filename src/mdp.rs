@@ -1,10 +1,7 @@
 use crate::environment::Reward;
-use itertools::{Itertools, Product};
 use std::cmp::Eq;
 use std::hash::Hash;
 
-type StateActionIter<'a, S, A> = Product<std::slice::Iter<'a, S>, std::slice::Iter<'a, A>>;
-
 pub type Probability = f64;
 
 pub trait MDP {
@@ -12,16 +9,43 @@ pub trait MDP {
     type Action: Copy + Hash + Eq;
 
     fn get_states(&self) -> &[Self::State];
+    /// Every action of the MDP, whether or not it is available everywhere
     fn get_actions(&self) -> &[Self::Action];
 
+    /// The actions that can be taken in `state`, a subset of `get_actions`
+    /// in the same order. A state without actions ends the episode.
+    fn available_actions(&self, _state: Self::State) -> &[Self::Action] {
+        self.get_actions()
+    }
+
     fn transition(&self, state: Self::State, action: Self::Action)
         -> &[(Self::State, Probability)];
     fn reward(&self, state: Self::State, action: Self::Action, next_state: Self::State) -> Reward;
 
-    fn state_actions(&self) -> StateActionIter<'_, Self::State, Self::Action> {
-        let states = self.get_states().into_iter();
-        let actions = self.get_actions().into_iter();
-        states.cartesian_product(actions)
+    /// Whether episodes end on reaching `state`. Terminal states are worth
+    /// nothing and no policy needs an action for them. By default a state is
+    /// terminal when none of its available actions leads anywhere.
+    fn is_terminal(&self, state: Self::State) -> bool {
+        self.available_actions(state)
+            .iter()
+            .all(|&action| self.transition(state, action).is_empty())
+    }
+
+    /// Where episodes start, as states with probabilities summing to one.
+    /// Uniform over all states by default.
+    fn initial_distribution(&self) -> Vec<(Self::State, Probability)> {
+        let states = self.get_states();
+        let prob = 1.0 / states.len() as f64;
+        states.iter().map(|&state| (state, prob)).collect()
+    }
+
+    /// Every state paired with each of its available actions
+    fn state_actions(&self) -> impl Iterator<Item = (&Self::State, &Self::Action)> {
+        self.get_states().iter().flat_map(move |state| {
+            self.available_actions(*state)
+                .iter()
+                .map(move |action| (state, action))
+        })
     }
 }
 
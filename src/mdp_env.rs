@@ -0,0 +1,175 @@
+use std::fmt::{Debug, Display};
+
+use rand::distributions::WeightedIndex;
+use rand::prelude::Distribution;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::environment::{Environment, Reward, SeedableEnvironment, StepResult};
+use crate::mdp::{Probability, MDP};
+
+/// Runs episodes of any MDP by sampling its transitions. Episodes start in
+/// a state drawn from the initial distribution and end in a terminal state.
+pub struct MDPEnv<M: MDP, R: Rng = StdRng> {
+    pub mdp: M,
+    state: M::State,
+    rng: R,
+}
+
+impl<M: MDP, R: Rng> MDPEnv<M, R> {
+    pub fn new(mdp: M, mut rng: R) -> Self {
+        let state = sample_initial_state(&mdp, &mut rng);
+        Self { mdp, state, rng }
+    }
+}
+
+/// Draws one of `probs`, which must hold a probability distribution.
+pub(crate) fn sample<T: Copy, R: Rng>(
+    probs: &[(T, Probability)],
+    rng: &mut R,
+) -> Result<T, String> {
+    let dist =
+        WeightedIndex::new(probs.iter().map(|&(_, prob)| prob)).map_err(|e| e.to_string())?;
+    Ok(probs[dist.sample(rng)].0)
+}
+
+pub(crate) fn sample_initial_state<M: MDP, R: Rng>(mdp: &M, rng: &mut R) -> M::State {
+    sample(&mdp.initial_distribution(), rng)
+        .expect("initial distribution is a probability distribution")
+}
+
+/// The next state and reward of taking `action` in `state`. Fails if the
+/// action leads nowhere because the episode has ended. Callers check that
+/// the action is available, as each decides what the agent may take.
+pub(crate) fn sample_transition<M: MDP, R: Rng>(
+    mdp: &M,
+    state: M::State,
+    action: M::Action,
+    rng: &mut R,
+) -> Result<(M::State, Reward), String> {
+    let next_state_probs = mdp.transition(state, action);
+    if next_state_probs.is_empty() {
+        return Err("the episode has ended".into());
+    }
+    let next_state = sample(next_state_probs, rng)?;
+    Ok((next_state, mdp.reward(state, action, next_state)))
+}
+
+impl<M, R> Environment for MDPEnv<M, R>
+where
+    M: MDP,
+    M::State: Debug,
+    M::Action: Debug + Display,
+    R: Rng,
+{
+    type State = M::State;
+    type Action = M::Action;
+
+    fn current_state(&self) -> &Self::State {
+        &self.state
+    }
+
+    fn available_actions(&self) -> Vec<Self::Action> {
+        self.mdp.available_actions(self.state).to_vec()
+    }
+
+    fn step(&mut self, action: &Self::Action) -> Result<StepResult<Self::State>, String> {
+        if !self.mdp.available_actions(self.state).contains(action) {
+            return Err(format!(
+                "{} is not available in state {:?}",
+                action, self.state
+            ));
+        }
+        let (next_state, reward) =
+            sample_transition(&self.mdp, self.state, *action, &mut self.rng)?;
+
+        self.state = next_state;
+
+        Ok(StepResult::new(
+            next_state,
+            reward,
+            self.mdp.is_terminal(next_state),
+        ))
+    }
+
+    fn reset(&mut self) -> &Self::State {
+        self.state = sample_initial_state(&self.mdp, &mut self.rng);
+        &self.state
+    }
+}
+
+impl<M, R> SeedableEnvironment for MDPEnv<M, R>
+where
+    M: MDP,
+    M::State: Debug,
+    M::Action: Debug + Display,
+    R: Rng + SeedableRng,
+{
+    fn seed(&mut self, seed: u64) {
+        self.rng = R::seed_from_u64(seed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{environment::Reward, mdp::Probability};
+
+    /// A corridor of three cells. The left end only allows moving right,
+    /// and the right end has no actions at all.
+    struct Corridor;
+
+    impl MDP for Corridor {
+        type State = u8;
+        type Action = char;
+
+        fn get_states(&self) -> &[u8] {
+            &[0, 1, 2]
+        }
+        fn get_actions(&self) -> &[char] {
+            &['l', 'r']
+        }
+        fn available_actions(&self, state: u8) -> &[char] {
+            match state {
+                0 => &['r'],
+                1 => &['l', 'r'],
+                _ => &[],
+            }
+        }
+        fn transition(&self, state: u8, action: char) -> &[(u8, Probability)] {
+            match (state, action) {
+                (0, 'r') => &[(1, 1.0)],
+                (1, 'l') => &[(0, 1.0)],
+                (1, 'r') => &[(2, 1.0)],
+                _ => &[],
+            }
+        }
+        fn reward(&self, _state: u8, _action: char, next_state: u8) -> Reward {
+            next_state as f64
+        }
+        fn initial_distribution(&self) -> Vec<(u8, Probability)> {
+            vec![(0, 1.0)]
+        }
+    }
+
+    #[test]
+    fn illegal_actions_are_errors() {
+        let mut env = MDPEnv::new(Corridor, StdRng::seed_from_u64(0));
+        assert_eq!(env.available_actions(), vec!['r']);
+        assert!(env.step(&'l').is_err());
+        assert_eq!(*env.current_state(), 0);
+
+        let result = env.step(&'r').unwrap();
+        assert_eq!((result.state, result.is_done), (1, false));
+        assert_eq!(env.available_actions(), vec!['l', 'r']);
+        let result = env.step(&'r').unwrap();
+        assert_eq!(
+            (result.state, result.reward, result.is_done),
+            (2, 2.0, true)
+        );
+        assert!(env.available_actions().is_empty());
+        assert!(env.step(&'r').is_err());
+
+        assert_eq!(*env.reset(), 0);
+    }
+}
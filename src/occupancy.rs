@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+
+use ndarray::{Array1, Array2};
+
+use crate::{
+    analysis, linalg,
+    mdp::{Probability, MDP},
+    policy::StochasticPolicy,
+    tabular::TabularMDP,
+};
+
+/// What happens after an episode ends in the chain induced by a policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AfterTermination {
+    /// Stay in the state where the episode ended
+    Stay,
+    /// Start a new episode from the initial distribution
+    Restart,
+}
+
+/// The state transition matrix of following `policy`, indexed like the
+/// states of `tabular`, where states without actions end the episode.
+fn policy_chain<S, A, P>(
+    tabular: &TabularMDP<S, A>,
+    initial: &[(S, Probability)],
+    policy: &P,
+    after_termination: AfterTermination,
+) -> Array2<f64>
+where
+    S: Copy + std::hash::Hash + Eq,
+    A: Copy + std::hash::Hash + Eq,
+    P: StochasticPolicy<S, A>,
+{
+    let n = tabular.num_states();
+    let mut transitions = Array2::zeros((n, n));
+    for (i, rows) in tabular.policy_rows(policy).iter().enumerate() {
+        if tabular.rows(i).is_empty() {
+            match after_termination {
+                AfterTermination::Stay => transitions[[i, i]] = 1.0,
+                AfterTermination::Restart => {
+                    for (state, prob) in initial {
+                        if let Some(j) = tabular.state_index(state) {
+                            transitions[[i, j]] += prob;
+                        }
+                    }
+                }
+            }
+            continue;
+        }
+        for &(row, action_prob) in rows {
+            let (next_states, probs) = tabular.successors(row);
+            for (&j, &prob) in next_states.iter().zip(probs) {
+                transitions[[i, j]] += action_prob * prob;
+            }
+        }
+    }
+    transitions
+}
+
+/// The discounted state-visitation distribution of `policy` from the
+/// initial distribution, d(s) = (1 - γ) Σₜ γᵗ P(sₜ = s). Episodes stay
+/// where they end, so terminal states collect the remaining mass and the
+/// distribution sums to one. Found by solving (I - γPᵀ)d = (1 - γ)μ.
+pub fn discounted_occupancy<M, P>(
+    mdp: &M,
+    policy: &P,
+    discount_rate: f64,
+) -> Result<HashMap<M::State, f64>, String>
+where
+    M: MDP,
+    P: StochasticPolicy<M::State, M::Action>,
+{
+    if !(0.0..1.0).contains(&discount_rate) {
+        return Err(format!(
+            "discounted occupancy needs a discount rate in [0, 1), got {}",
+            discount_rate
+        ));
+    }
+    let tabular = TabularMDP::from_mdp(mdp);
+    let initial = mdp.initial_distribution();
+    let transitions = policy_chain(&tabular, &initial, policy, AfterTermination::Stay);
+
+    let n = tabular.num_states();
+    let mut mu = Array1::zeros(n);
+    for (state, prob) in &initial {
+        if let Some(s) = tabular.state_index(state) {
+            mu[s] += (1.0 - discount_rate) * prob;
+        }
+    }
+    let a = Array2::<f64>::eye(n) - discount_rate * transitions.t().to_owned();
+    let occupancy = linalg::solve(a, &mu)?;
+    Ok(tabular.to_state_values(&occupancy.to_vec()))
+}
+
+/// The long-run fraction of time `policy` spends in each state, where every
+/// episode is followed by a new one from the initial distribution. Fails
+/// unless the chain has a single recurrent class, which makes it unique.
+pub fn stationary_distribution<M, P>(mdp: &M, policy: &P) -> Result<HashMap<M::State, f64>, String>
+where
+    M: MDP,
+    P: StochasticPolicy<M::State, M::Action>,
+{
+    let tabular = TabularMDP::from_mdp(mdp);
+    let initial = mdp.initial_distribution();
+    let transitions = policy_chain(&tabular, &initial, policy, AfterTermination::Restart);
+
+    let n = tabular.num_states();
+    let successors: Vec<Vec<usize>> = (0..n)
+        .map(|i| (0..n).filter(|&j| transitions[[i, j]] > 0.0).collect())
+        .collect();
+    let num_classes = analysis::components(&successors)
+        .iter()
+        .filter(|component| {
+            component
+                .iter()
+                .all(|&i| successors[i].iter().all(|j| component.contains(j)))
+        })
+        .count();
+    if num_classes != 1 {
+        return Err(format!(
+            "the chain under the policy has {} recurrent classes",
+            num_classes
+        ));
+    }
+
+    // πᵀ(P - I) = 0 with one equation swapped for Σπ = 1
+    let mut a = transitions.t().to_owned() - Array2::<f64>::eye(n);
+    a.row_mut(n - 1).fill(1.0);
+    let mut b = Array1::zeros(n);
+    b[n - 1] = 1.0;
+    let stationary = linalg::solve(a, &b)?;
+    Ok(tabular.to_state_values(&stationary.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::Reward,
+        grid_world::{GridWorld, GridWorldMDP, FROZEN_LAKE_4X4},
+        policy::{MDPPolicy, StochasticMDPPolicy},
+        policy_iteration::value_iteration,
+    };
+
+    /// Two states that swap with probability 0.3 from state 0 and 0.6 from
+    /// state 1. Episodes start in state 0 and never end.
+    struct Flip;
+
+    impl MDP for Flip {
+        type State = u8;
+        type Action = ();
+
+        fn get_states(&self) -> &[u8] {
+            &[0, 1]
+        }
+        fn get_actions(&self) -> &[()] {
+            &[()]
+        }
+        fn transition(&self, state: u8, _action: ()) -> &[(u8, Probability)] {
+            match state {
+                0 => &[(0, 0.7), (1, 0.3)],
+                _ => &[(0, 0.6), (1, 0.4)],
+            }
+        }
+        fn reward(&self, _state: u8, _action: (), _next_state: u8) -> Reward {
+            0.0
+        }
+        fn initial_distribution(&self) -> Vec<(u8, Probability)> {
+            vec![(0, 1.0)]
+        }
+    }
+
+    #[test]
+    fn two_state_chain() {
+        let policy = MDPPolicy::<Flip>::new(HashMap::from([(0, ()), (1, ())]));
+        let stationary = stationary_distribution(&Flip, &policy).unwrap();
+        assert!((stationary[&0] - 2.0 / 3.0).abs() < 1e-12);
+
+        // sum the series directly
+        let gamma = 0.9;
+        let mut distribution = [1.0, 0.0];
+        let mut expected = [0.0, 0.0];
+        let mut weight = 1.0 - gamma;
+        for _ in 0..1000 {
+            expected[0] += weight * distribution[0];
+            expected[1] += weight * distribution[1];
+            distribution = [
+                0.7 * distribution[0] + 0.6 * distribution[1],
+                0.3 * distribution[0] + 0.4 * distribution[1],
+            ];
+            weight *= gamma;
+        }
+        let occupancy = discounted_occupancy(&Flip, &policy, gamma).unwrap();
+        assert!((occupancy[&0] - expected[0]).abs() < 1e-12);
+        assert!((occupancy[&1] - expected[1]).abs() < 1e-12);
+        assert!(discounted_occupancy(&Flip, &policy, 1.0).is_err());
+    }
+
+    #[test]
+    fn frozen_lake_occupancy() {
+        let mdp = GridWorldMDP::new(GridWorld::from_map(&FROZEN_LAKE_4X4, 2.0 / 3.0, 0.9).unwrap());
+        let policy = value_iteration(&mdp, 0.9, 1e-10).unwrap().policy;
+
+        let occupancy = discounted_occupancy(&mdp, &policy, 0.9).unwrap();
+        assert!((occupancy.values().sum::<f64>() - 1.0).abs() < 1e-9);
+        let stationary = stationary_distribution(&mdp, &policy).unwrap();
+        assert!((stationary.values().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(stationary.values().all(|&p| p >= -1e-12));
+        // the start is visited at least once per episode
+        assert!(stationary[&0] > stationary[&15]);
+
+        let rendered = mdp.grid_world.render_heatmap(&stationary);
+        assert_eq!(rendered.lines().count(), 5);
+        assert!(rendered.starts_with("██"));
+
+        // a random walk with a short horizon rarely ends up at the goal
+        let uniform = StochasticMDPPolicy::uniform(&mdp);
+        let short = discounted_occupancy(&mdp, &uniform, 0.5).unwrap();
+        assert!(short[&15] < occupancy[&15]);
+    }
+}
@@ -0,0 +1,570 @@
+use std::collections::HashMap;
+
+use crate::{
+    mdp::Probability,
+    policy::Policy,
+    pomdp::{Belief, POMDP},
+    stopping::{ConvergenceError, Status, StoppingCriteria},
+};
+
+/// One linear piece of a POMDP value function, V(b) ≥ Σ_s b(s) α(s), with
+/// the action that starts the conditional plan it is the value of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlphaVector<A> {
+    pub action: A,
+    /// α(s), indexed like `get_states`
+    pub values: Vec<f64>,
+}
+
+/// The policy of a set of alpha-vectors: in each belief, act like the
+/// vector with the highest value there.
+pub struct AlphaVectorPolicy<P: POMDP> {
+    state_indices: HashMap<P::State, usize>,
+    pub alpha_vectors: Vec<AlphaVector<P::Action>>,
+}
+
+impl<P: POMDP> AlphaVectorPolicy<P> {
+    fn to_vector(&self, belief: &Belief<P::State>) -> Vec<f64> {
+        let mut probs = vec![0.0; self.state_indices.len()];
+        for (state, &prob) in belief {
+            if let Some(&s) = self.state_indices.get(state) {
+                probs[s] += prob;
+            }
+        }
+        probs
+    }
+
+    /// The first vector with the highest value in `belief`, and that value.
+    fn best(&self, belief: &[f64]) -> (&AlphaVector<P::Action>, f64) {
+        let mut best = (
+            &self.alpha_vectors[0],
+            dot(belief, &self.alpha_vectors[0].values),
+        );
+        for alpha in &self.alpha_vectors[1..] {
+            let value = dot(belief, &alpha.values);
+            if value > best.1 {
+                best = (alpha, value);
+            }
+        }
+        best
+    }
+
+    /// The value of `belief` according to the alpha-vectors
+    pub fn value(&self, belief: &Belief<P::State>) -> f64 {
+        self.best(&self.to_vector(belief)).1
+    }
+}
+
+impl<P: POMDP> Policy<Belief<P::State>, P::Action> for AlphaVectorPolicy<P> {
+    fn get_action(&self, belief: &Belief<P::State>) -> P::Action {
+        self.best(&self.to_vector(belief)).0.action
+    }
+}
+
+/// The result of point-based value iteration.
+pub struct PBVISolution<P: POMDP> {
+    pub policy: AlphaVectorPolicy<P>,
+    /// The beliefs whose values were backed up
+    pub belief_points: Vec<Belief<P::State>>,
+    /// Value of the initial belief
+    pub initial_value: f64,
+    /// Backups of the whole belief set, over every expansion
+    pub num_iterations: usize,
+    /// Largest change in the value of a belief point at every iteration
+    pub residuals: Vec<f64>,
+}
+
+/// A converged PBVI solution, or the last iterate of one that ran out of budget
+pub type PBVIResult<P> = Result<PBVISolution<P>, ConvergenceError<PBVISolution<P>>>;
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn l1_distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| (a - b).abs()).sum()
+}
+
+/// A POMDP with states, actions and observations replaced by indices.
+/// Actions that are unavailable in a state, and every action in terminal
+/// states, lead nowhere and earn nothing; backups only consider the actions
+/// available in every state a belief holds.
+struct Model {
+    num_states: usize,
+    /// `available[a][s]`, whether `a` may be taken in `s`. Every action may
+    /// be taken in a terminal state, where none has any effect.
+    available: Vec<Vec<bool>>,
+    /// `transitions[a][s]` lists the successors of `s` under `a`
+    transitions: Vec<Vec<Vec<(usize, Probability)>>>,
+    /// `rewards[a][s]`, the expected reward of taking `a` in `s`
+    rewards: Vec<Vec<f64>>,
+    /// `observations[a][s'][o]`, the probability of `o` on reaching `s'` with `a`
+    observations: Vec<Vec<Vec<Probability>>>,
+}
+
+impl Model {
+    fn new<P: POMDP>(pomdp: &P) -> Self {
+        let states = pomdp.get_states();
+        let state_indices: HashMap<P::State, usize> =
+            states.iter().enumerate().map(|(i, &s)| (s, i)).collect();
+        let observation_indices: HashMap<P::Observation, usize> = pomdp
+            .get_observations()
+            .iter()
+            .enumerate()
+            .map(|(i, &o)| (o, i))
+            .collect();
+
+        let mut available = vec![];
+        let mut transitions = vec![];
+        let mut rewards = vec![];
+        let mut observations = vec![];
+        for &action in pomdp.get_actions() {
+            available.push(
+                states
+                    .iter()
+                    .map(|&state| {
+                        pomdp.is_terminal(state) || pomdp.available_actions(state).contains(&action)
+                    })
+                    .collect(),
+            );
+            let (action_transitions, action_rewards) = states
+                .iter()
+                .map(|&state| {
+                    if pomdp.is_terminal(state) || !pomdp.available_actions(state).contains(&action)
+                    {
+                        return (vec![], 0.0);
+                    }
+                    let mut successors = vec![];
+                    let mut reward = 0.0;
+                    for &(next_state, prob) in pomdp.transition(state, action) {
+                        reward += prob * pomdp.reward(state, action, next_state);
+                        if let Some(&j) = state_indices.get(&next_state) {
+                            successors.push((j, prob));
+                        }
+                    }
+                    (successors, reward)
+                })
+                .unzip();
+            transitions.push(action_transitions);
+            rewards.push(action_rewards);
+
+            observations.push(
+                states
+                    .iter()
+                    .map(|&next_state| {
+                        let mut probs = vec![0.0; observation_indices.len()];
+                        for &(observation, prob) in pomdp.observation(action, next_state) {
+                            if let Some(&o) = observation_indices.get(&observation) {
+                                probs[o] += prob;
+                            }
+                        }
+                        probs
+                    })
+                    .collect(),
+            );
+        }
+
+        Self {
+            num_states: states.len(),
+            available,
+            transitions,
+            rewards,
+            observations,
+        }
+    }
+
+    fn num_actions(&self) -> usize {
+        self.rewards.len()
+    }
+
+    fn num_observations(&self) -> usize {
+        self.observations
+            .first()
+            .and_then(|observations| observations.first())
+            .map_or(0, Vec::len)
+    }
+
+    /// The actions available in every state `belief` holds, or every action
+    /// if there is none, as the belief cannot be acted on exactly then.
+    fn available_actions(&self, belief: &[f64]) -> Vec<usize> {
+        let available: Vec<usize> = (0..self.num_actions())
+            .filter(|&a| {
+                belief
+                    .iter()
+                    .zip(&self.available[a])
+                    .all(|(&prob, &available)| prob == 0.0 || available)
+            })
+            .collect();
+        if available.is_empty() {
+            (0..self.num_actions()).collect()
+        } else {
+            available
+        }
+    }
+
+    /// The belief after taking `a` in `belief` and observing `o`, unless
+    /// the observation is impossible.
+    fn update(&self, belief: &[f64], a: usize, o: usize) -> Option<Vec<f64>> {
+        let mut next_belief = vec![0.0; self.num_states];
+        for (s, &prob) in belief.iter().enumerate() {
+            for &(j, next_prob) in &self.transitions[a][s] {
+                next_belief[j] += prob * next_prob;
+            }
+        }
+        for (j, prob) in next_belief.iter_mut().enumerate() {
+            *prob *= self.observations[a][j][o];
+        }
+        let total: f64 = next_belief.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        next_belief.iter_mut().for_each(|prob| *prob /= total);
+        Some(next_belief)
+    }
+
+    /// g(s) = γ Σ_s' T(s' | s, a) O(o | a, s') α(s'), the discounted value
+    /// of continuing with `alpha` after taking `a` and observing `o`.
+    fn project(&self, alpha: &[f64], a: usize, o: usize, discount_rate: f64) -> Vec<f64> {
+        (0..self.num_states)
+            .map(|s| {
+                discount_rate
+                    * self.transitions[a][s]
+                        .iter()
+                        .map(|&(j, prob)| prob * self.observations[a][j][o] * alpha[j])
+                        .sum::<f64>()
+            })
+            .collect()
+    }
+}
+
+/// The point-based Bellman backup of every belief, each producing the
+/// alpha-vector that is best at that belief, without duplicates. A belief
+/// keeps its old vector if the backup is worse there, as in Perseus, so the
+/// values of the beliefs only go up and the iteration converges.
+fn backup(
+    model: &Model,
+    beliefs: &[Vec<f64>],
+    alphas: &[(usize, Vec<f64>)],
+    discount_rate: f64,
+) -> Vec<(usize, Vec<f64>)> {
+    // projections[a][o][k] of every alpha-vector k, shared by all beliefs
+    let projections: Vec<Vec<Vec<Vec<f64>>>> = (0..model.num_actions())
+        .map(|a| {
+            (0..model.num_observations())
+                .map(|o| {
+                    alphas
+                        .iter()
+                        .map(|(_, alpha)| model.project(alpha, a, o, discount_rate))
+                        .collect()
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut backed_up: Vec<(usize, Vec<f64>)> = vec![];
+    for belief in beliefs {
+        let mut best: Option<(usize, Vec<f64>, f64)> = None;
+        for a in model.available_actions(belief) {
+            let mut alpha = model.rewards[a].clone();
+            for observation_projections in &projections[a] {
+                let g = observation_projections
+                    .iter()
+                    .reduce(|best, g| {
+                        if dot(belief, g) > dot(belief, best) {
+                            g
+                        } else {
+                            best
+                        }
+                    })
+                    .expect("at least one alpha-vector");
+                alpha.iter_mut().zip(g).for_each(|(alpha, g)| *alpha += g);
+            }
+            let value = dot(belief, &alpha);
+            if best
+                .as_ref()
+                .is_none_or(|(_, _, best_value)| value > *best_value)
+            {
+                best = Some((a, alpha, value));
+            }
+        }
+        let (mut a, mut alpha, value) = best.expect("at least one action");
+        // keep the old vector where it is better, so values never decrease
+        let (old_a, old_alpha) = alphas
+            .iter()
+            .reduce(|best, other| {
+                if dot(belief, &other.1) > dot(belief, &best.1) {
+                    other
+                } else {
+                    best
+                }
+            })
+            .expect("at least one alpha-vector");
+        if dot(belief, old_alpha) > value {
+            (a, alpha) = (*old_a, old_alpha.clone());
+        }
+        if !backed_up.iter().any(|(_, other)| *other == alpha) {
+            backed_up.push((a, alpha));
+        }
+    }
+    backed_up
+}
+
+/// Adds to `beliefs` one successor of each belief, the one reachable in a
+/// single step that is farthest from every belief already in the set.
+fn expand(model: &Model, beliefs: &mut Vec<Vec<f64>>) {
+    for i in 0..beliefs.len() {
+        let mut farthest: Option<(Vec<f64>, f64)> = None;
+        for a in model.available_actions(&beliefs[i]) {
+            for o in 0..model.num_observations() {
+                let Some(next_belief) = model.update(&beliefs[i], a, o) else {
+                    continue;
+                };
+                let distance = beliefs
+                    .iter()
+                    .map(|belief| l1_distance(belief, &next_belief))
+                    .fold(f64::INFINITY, f64::min);
+                if farthest
+                    .as_ref()
+                    .is_none_or(|(_, farthest)| distance > *farthest)
+                {
+                    farthest = Some((next_belief, distance));
+                }
+            }
+        }
+        if let Some((next_belief, distance)) = farthest {
+            if distance > 1e-9 {
+                beliefs.push(next_belief);
+            }
+        }
+    }
+}
+
+/// Point-based value iteration (Pineau, Gordon and Thrun, 2003). Bellman
+/// backups are only computed at a finite set of beliefs, each producing the
+/// alpha-vector that is best there, which keeps the value function small.
+/// The set starts from the initial belief and grows `num_expansions` times
+/// by adding the most distant one-step successor of every belief, backing
+/// up the set until its values settle according to `stopping` in between.
+/// The budgets in `stopping` cover the whole run: `max_iterations` caps the
+/// backups of the belief set over every expansion together.
+///
+/// The values start from the lower bound min(R, 0) / (1 - γ), so the
+/// discount rate must be less than one.
+pub fn pbvi<P>(
+    pomdp: &P,
+    discount_rate: f64,
+    num_expansions: usize,
+    stopping: impl Into<StoppingCriteria>,
+) -> PBVIResult<P>
+where
+    P: POMDP,
+{
+    assert!(
+        (0.0..1.0).contains(&discount_rate),
+        "discount rate must be in [0, 1)"
+    );
+
+    let model = Model::new(pomdp);
+    let stopper = stopping.into().start(discount_rate);
+    let states = pomdp.get_states();
+    let actions = pomdp.get_actions();
+    let state_indices: HashMap<P::State, usize> =
+        states.iter().enumerate().map(|(i, &s)| (s, i)).collect();
+
+    let mut initial_belief = vec![0.0; states.len()];
+    for (state, prob) in pomdp.initial_distribution() {
+        if let Some(&s) = state_indices.get(&state) {
+            initial_belief[s] += prob;
+        }
+    }
+    let mut beliefs = vec![initial_belief.clone()];
+
+    let min_reward = model.rewards.iter().flatten().copied().fold(0.0, f64::min);
+    let mut alphas = vec![(0, vec![min_reward / (1.0 - discount_rate); states.len()])];
+
+    let value = |alphas: &[(usize, Vec<f64>)], belief: &[f64]| {
+        alphas
+            .iter()
+            .map(|(_, alpha)| dot(belief, alpha))
+            .fold(f64::NEG_INFINITY, f64::max)
+    };
+
+    let mut num_iterations = 0;
+    let mut residuals = vec![];
+    for expansion in 0..=num_expansions {
+        if expansion > 0 {
+            expand(&model, &mut beliefs);
+        }
+
+        let status = loop {
+            num_iterations += 1;
+
+            let backed_up = backup(&model, &beliefs, &alphas, discount_rate);
+            let residual = beliefs
+                .iter()
+                .map(|belief| (value(&backed_up, belief) - value(&alphas, belief)).abs())
+                .fold(0.0, f64::max);
+            residuals.push(residual);
+            alphas = backed_up;
+
+            let status = stopper.status(num_iterations, residual);
+            if !status.is_running() {
+                break status;
+            }
+        };
+
+        if expansion == num_expansions || !matches!(status, Status::Converged) {
+            let solution = PBVISolution {
+                initial_value: value(&alphas, &initial_belief),
+                policy: AlphaVectorPolicy {
+                    state_indices,
+                    alpha_vectors: alphas
+                        .into_iter()
+                        .map(|(a, values)| AlphaVector {
+                            action: actions[a],
+                            values,
+                        })
+                        .collect(),
+                },
+                belief_points: beliefs
+                    .iter()
+                    .map(|belief| {
+                        belief
+                            .iter()
+                            .enumerate()
+                            .filter(|&(_, &prob)| prob > 0.0)
+                            .map(|(s, &prob)| (states[s], prob))
+                            .collect()
+                    })
+                    .collect(),
+                num_iterations,
+                residuals,
+            };
+            return status.into_result(num_iterations, solution);
+        }
+    }
+    unreachable!("the last expansion returns")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::{Environment, Reward},
+        mdp::MDP,
+        pomdp::BeliefFilter,
+        pomdp_env::ObservationEnv,
+        stopping::StopReason,
+        tiger::{Tiger, TigerAction, TigerState},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const WALK: u8 = 0;
+    const WAIT: u8 = 1;
+
+    /// Walking moves one step along a corridor from state 0 through state 1
+    /// to the terminal state 2, at a cost of 1 per step. Waiting costs 2 and
+    /// is only possible in state 0. Nothing is ever observed.
+    struct Corridor;
+
+    impl MDP for Corridor {
+        type State = u8;
+        type Action = u8;
+
+        fn get_states(&self) -> &[u8] {
+            &[0, 1, 2]
+        }
+        fn get_actions(&self) -> &[u8] {
+            &[WALK, WAIT]
+        }
+        fn available_actions(&self, state: u8) -> &[u8] {
+            match state {
+                0 => &[WALK, WAIT],
+                1 => &[WALK],
+                _ => &[],
+            }
+        }
+        fn transition(&self, state: u8, action: u8) -> &[(u8, Probability)] {
+            match (state, action) {
+                (0, WALK) => &[(1, 1.0)],
+                (0, _) => &[(0, 1.0)],
+                (1, _) => &[(2, 1.0)],
+                _ => &[],
+            }
+        }
+        fn reward(&self, _state: u8, action: u8, _next_state: u8) -> Reward {
+            if action == WALK {
+                -1.0
+            } else {
+                -2.0
+            }
+        }
+        fn initial_distribution(&self) -> Vec<(u8, Probability)> {
+            vec![(0, 1.0)]
+        }
+    }
+
+    impl POMDP for Corridor {
+        type Observation = ();
+
+        fn get_observations(&self) -> &[()] {
+            &[()]
+        }
+        fn observation(&self, _action: u8, _next_state: u8) -> &[((), Probability)] {
+            &[((), 1.0)]
+        }
+    }
+
+    #[test]
+    fn never_takes_unavailable_actions() {
+        // waiting in state 1 would be free if it were allowed
+        let solution = pbvi(&Corridor, 0.9, 2, 1e-9).unwrap();
+        assert!((solution.initial_value - (-1.0 - 0.9)).abs() < 1e-6);
+        let policy = &solution.policy;
+        assert_eq!(policy.get_action(&Belief::from([(0, 1.0)])), WALK);
+        assert_eq!(policy.get_action(&Belief::from([(1, 1.0)])), WALK);
+    }
+
+    #[test]
+    fn solves_tiger() {
+        let solution = pbvi(&Tiger, 0.95, 5, 1e-6).unwrap();
+        assert!((solution.initial_value - 19.37).abs() < 0.01);
+
+        // listen when unsure, open the other door when fairly sure
+        let policy = &solution.policy;
+        let belief =
+            |left: f64| Belief::from([(TigerState::Left, left), (TigerState::Right, 1.0 - left)]);
+        assert_eq!(policy.get_action(&belief(0.5)), TigerAction::Listen);
+        assert_eq!(policy.get_action(&belief(0.99)), TigerAction::OpenRight);
+        assert_eq!(policy.get_action(&belief(0.01)), TigerAction::OpenLeft);
+    }
+
+    #[test]
+    fn iteration_budget_covers_every_expansion() {
+        let solution = pbvi(&Tiger, 0.95, 3, 1e-6).unwrap();
+        let total = solution.num_iterations;
+
+        // each expansion alone takes fewer backups than all of them together
+        let criteria = StoppingCriteria::residual(1e-6).with_max_iterations(total - 1);
+        let error = pbvi(&Tiger, 0.95, 3, criteria).err().unwrap();
+        assert_eq!(error.reason, StopReason::MaxIterations(total - 1));
+        assert_eq!(error.last_iterate.num_iterations, total - 1);
+    }
+
+    #[test]
+    fn acts_on_tracked_beliefs() {
+        let solution = pbvi(&Tiger, 0.95, 5, 1e-6).unwrap();
+        let mut env = ObservationEnv::new(Tiger, StdRng::seed_from_u64(0));
+        let mut filter = BeliefFilter::new(&Tiger);
+
+        let num_steps = 5000;
+        let mut total_reward = 0.0;
+        for _ in 0..num_steps {
+            let action = solution.policy.get_action(filter.belief());
+            let result = env.step(&action).unwrap();
+            total_reward += result.reward;
+            filter.update(action, result.state.unwrap()).unwrap();
+        }
+        assert!(total_reward / num_steps as f64 > 0.5);
+    }
+}
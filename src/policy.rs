@@ -1,11 +1,36 @@
 use std::collections::HashMap;
 
-use crate::mdp::MDP;
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+
+use crate::{
+    mdp::{Probability, MDP},
+    solution::ActionValues,
+};
 
 pub trait Policy<S, A> {
     fn get_action(&self, state: &S) -> A;
 }
 
+/// A policy that picks actions at random according to a distribution that
+/// depends on the state.
+pub trait StochasticPolicy<S, A> {
+    /// π(a | s) for every action with non-zero probability
+    fn action_probs(&self, state: &S) -> Vec<(A, Probability)>;
+
+    fn sample_action<R: Rng + ?Sized>(&self, state: &S, rng: &mut R) -> A {
+        let mut action_probs = self.action_probs(state);
+        let dist = WeightedIndex::new(action_probs.iter().map(|&(_, prob)| prob))
+            .expect("valid action distribution");
+        action_probs.swap_remove(dist.sample(rng)).0
+    }
+}
+
+/// A policy whose choice may also depend on the timestep, as needed when
+/// only a fixed number of steps remain.
+pub trait NonStationaryPolicy<S, A> {
+    fn get_action_at(&self, timestep: usize, state: &S) -> A;
+}
+
 pub struct MDPPolicy<M: MDP> {
     state_actions: HashMap<M::State, M::Action>,
 }
@@ -18,6 +43,247 @@ impl<M: MDP> MDPPolicy<M> {
 
 impl<M: MDP> Policy<M::State, M::Action> for MDPPolicy<M> {
     fn get_action(&self, state: &M::State) -> M::Action {
-        self.state_actions[state].clone()
+        self.state_actions[state]
+    }
+}
+
+/// A deterministic policy is the special case that puts all mass on one action.
+impl<M: MDP> StochasticPolicy<M::State, M::Action> for MDPPolicy<M> {
+    fn action_probs(&self, state: &M::State) -> Vec<(M::Action, Probability)> {
+        vec![(self.get_action(state), 1.0)]
+    }
+
+    fn sample_action<R: Rng + ?Sized>(&self, state: &M::State, _rng: &mut R) -> M::Action {
+        self.get_action(state)
+    }
+}
+
+impl<M: MDP> NonStationaryPolicy<M::State, M::Action> for MDPPolicy<M> {
+    fn get_action_at(&self, _timestep: usize, state: &M::State) -> M::Action {
+        self.get_action(state)
+    }
+}
+
+/// A stochastic policy over the states and actions of an MDP.
+pub struct StochasticMDPPolicy<M: MDP> {
+    action_probs: HashMap<M::State, Vec<(M::Action, Probability)>>,
+}
+
+/// The actions a policy can choose between in `state`: none once the
+/// episode is over.
+fn choices<M: MDP>(mdp: &M, state: M::State) -> &[M::Action] {
+    if mdp.is_terminal(state) {
+        &[]
+    } else {
+        mdp.available_actions(state)
+    }
+}
+
+impl<M: MDP> StochasticMDPPolicy<M> {
+    pub fn new(action_probs: HashMap<M::State, Vec<(M::Action, Probability)>>) -> Self {
+        Self { action_probs }
+    }
+
+    /// Every action equally likely in every state.
+    pub fn uniform(mdp: &M) -> Self {
+        let action_probs = mdp
+            .get_states()
+            .iter()
+            .map(|&state| {
+                let actions = choices(mdp, state);
+                let prob = 1.0 / actions.len() as f64;
+                (state, actions.iter().map(|&a| (a, prob)).collect())
+            })
+            .collect();
+        Self::new(action_probs)
+    }
+
+    /// Epsilon-soft version of `policy`: a uniformly random available action
+    /// with probability `epsilon`, otherwise the action `policy` would take.
+    /// `policy` is never asked for an action in terminal states.
+    pub fn epsilon_greedy<P>(mdp: &M, policy: &P, epsilon: f64) -> Self
+    where
+        P: Policy<M::State, M::Action>,
+    {
+        let action_probs = mdp
+            .get_states()
+            .iter()
+            .map(|&state| {
+                let actions = choices(mdp, state);
+                if actions.is_empty() {
+                    return (state, vec![]);
+                }
+                let explore_prob = epsilon / actions.len() as f64;
+                let greedy_action = policy.get_action(&state);
+                let probs = actions
+                    .iter()
+                    .map(|&a| {
+                        let exploit_prob = if a == greedy_action {
+                            1.0 - epsilon
+                        } else {
+                            0.0
+                        };
+                        (a, explore_prob + exploit_prob)
+                    })
+                    .collect();
+                (state, probs)
+            })
+            .collect();
+        Self::new(action_probs)
+    }
+
+    /// Boltzmann policy π(a | s) ∝ exp(Q(s, a) / temperature).
+    pub fn softmax(
+        mdp: &M,
+        action_values: &ActionValues<M::State, M::Action>,
+        temperature: f64,
+    ) -> Self {
+        let action_probs = mdp
+            .get_states()
+            .iter()
+            .map(|&state| {
+                let actions = choices(mdp, state);
+                let q = &action_values[&state];
+                // shift by the max so the exponentials cannot overflow
+                let max_q = actions
+                    .iter()
+                    .map(|a| q[a])
+                    .fold(f64::NEG_INFINITY, f64::max);
+                let weights: Vec<f64> = actions
+                    .iter()
+                    .map(|a| ((q[a] - max_q) / temperature).exp())
+                    .collect();
+                let total: f64 = weights.iter().sum();
+                let probs = actions
+                    .iter()
+                    .zip(weights)
+                    .map(|(&a, w)| (a, w / total))
+                    .collect();
+                (state, probs)
+            })
+            .collect();
+        Self::new(action_probs)
+    }
+}
+
+impl<M: MDP> StochasticPolicy<M::State, M::Action> for StochasticMDPPolicy<M> {
+    fn action_probs(&self, state: &M::State) -> Vec<(M::Action, Probability)> {
+        self.action_probs[state]
+            .iter()
+            .copied()
+            .filter(|&(_, prob)| prob > 0.0)
+            .collect()
+    }
+}
+
+/// A separate deterministic policy for each timestep of a finite horizon.
+pub struct TimeIndexedPolicy<M: MDP> {
+    state_actions: Vec<HashMap<M::State, M::Action>>,
+}
+
+impl<M: MDP> TimeIndexedPolicy<M> {
+    pub fn new(state_actions: Vec<HashMap<M::State, M::Action>>) -> Self {
+        assert!(!state_actions.is_empty(), "at least one timestep");
+        Self { state_actions }
+    }
+
+    pub fn horizon(&self) -> usize {
+        self.state_actions.len()
+    }
+}
+
+impl<M: MDP> NonStationaryPolicy<M::State, M::Action> for TimeIndexedPolicy<M> {
+    /// Timesteps at or past the horizon reuse the final step's action.
+    fn get_action_at(&self, timestep: usize, state: &M::State) -> M::Action {
+        let t = timestep.min(self.horizon() - 1);
+        self.state_actions[t][state]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        direction::Direction,
+        grid_world::{GridWorld, GridWorldMDP, FROZEN_LAKE_4X4},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn deterministic_policy_as_stochastic() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 0.0, 1.0).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let policy = MDPPolicy::<GridWorldMDP>::new(
+            mdp.get_states()
+                .iter()
+                .map(|&s| (s, Direction::Down))
+                .collect(),
+        );
+        assert_eq!(policy.action_probs(&3), vec![(Direction::Down, 1.0)]);
+        assert_eq!(
+            policy.sample_action(&3, &mut StdRng::seed_from_u64(0)),
+            Direction::Down
+        );
+    }
+
+    #[test]
+    fn epsilon_greedy_probabilities() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 0.0, 1.0).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let greedy = MDPPolicy::<GridWorldMDP>::new(
+            mdp.get_states()
+                .iter()
+                .map(|&s| (s, Direction::Left))
+                .collect(),
+        );
+        let policy = StochasticMDPPolicy::epsilon_greedy(&mdp, &greedy, 0.2);
+
+        let probs = policy.action_probs(&0);
+        assert_eq!(probs.len(), 4);
+        assert!((probs.iter().map(|(_, p)| p).sum::<f64>() - 1.0).abs() < 1e-12);
+        for (action, prob) in probs {
+            let expected = if action == Direction::Left {
+                0.85
+            } else {
+                0.05
+            };
+            assert!((prob - expected).abs() < 1e-12);
+        }
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let lefts = (0..10000)
+            .filter(|_| policy.sample_action(&0, &mut rng) == Direction::Left)
+            .count();
+        assert!((lefts as f64 / 10000.0 - 0.85).abs() < 0.02);
+    }
+
+    #[test]
+    fn softmax_probabilities() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 0.0, 1.0).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let action_values = mdp
+            .get_states()
+            .iter()
+            .map(|&s| {
+                let q = Direction::all()
+                    .into_iter()
+                    .map(|a| (a, if a == Direction::Up { 1000.0 } else { 999.0 }))
+                    .collect();
+                (s, q)
+            })
+            .collect();
+
+        let hot = StochasticMDPPolicy::softmax(&mdp, &action_values, 1e6);
+        assert!(hot
+            .action_probs(&0)
+            .iter()
+            .all(|&(_, p)| (p - 0.25).abs() < 1e-3));
+
+        let policy = StochasticMDPPolicy::softmax(&mdp, &action_values, 1.0);
+        let e = std::f64::consts::E;
+        for (action, prob) in policy.action_probs(&0) {
+            let expected = if action == Direction::Up { e } else { 1.0 } / (e + 3.0);
+            assert!((prob - expected).abs() < 1e-12);
+        }
     }
 }
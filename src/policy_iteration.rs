@@ -1,208 +1,1048 @@
-use rand::{rngs::ThreadRng, seq::SliceRandom};
+use ndarray::{Array1, Array2};
+use rand::{
+    rngs::StdRng,
+    seq::{IteratorRandom, SliceRandom},
+    Rng, SeedableRng,
+};
 
 use crate::{
-    mdp::MDP,
-    policy::{MDPPolicy, Policy},
+    linalg,
+    mdp::{Probability, MDP},
+    policy::StochasticPolicy,
+    solution::{EvaluationResult, Solution, SolverResult},
+    stopping::{ConvergenceError, StopReason, Stopper, StoppingCriteria},
+    tabular::TabularMDP,
 };
 use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The rows a policy takes in each state with their probabilities, indexed
+/// like the states of a [`TabularMDP`].
+type PolicyRows = Vec<Vec<(usize, Probability)>>;
+
+/// A deterministic policy taking one row in each state that has any.
+fn deterministic(rows: &[Option<usize>]) -> PolicyRows {
+    rows.iter()
+        .map(|&row| row.map(|row| (row, 1.0)).into_iter().collect())
+        .collect()
+}
 
-fn evaluate_policy<M, P>(
+/// A policy choosing uniformly among the given rows in each state.
+fn uniform(rows: &[Vec<usize>]) -> PolicyRows {
+    rows.iter()
+        .map(|rows| {
+            let prob = 1.0 / rows.len() as f64;
+            rows.iter().map(|&row| (row, prob)).collect()
+        })
+        .collect()
+}
+
+/// Iterative policy evaluation, sweeping until the values settle according
+/// to `stopping`. Returns the values and the number of sweeps taken.
+pub fn evaluate_policy<M, P>(
     mdp: &M,
     policy: &P,
     discount_rate: f64,
-    threshold: f64,
-) -> (HashMap<M::State, f64>, usize)
+    stopping: impl Into<StoppingCriteria>,
+) -> EvaluationResult<M::State>
 where
     M: MDP,
-    P: Policy<M::State, M::Action>,
+    P: StochasticPolicy<M::State, M::Action>,
 {
-    let mut state_values_prev: HashMap<M::State, f64> = mdp
-        .get_states()
-        .into_iter()
-        .map(|s| (s.clone(), 0.0))
-        .collect();
+    let tabular = TabularMDP::from_mdp(mdp);
+    let policy = tabular.policy_rows(policy);
+    let stopper = stopping.into().start(discount_rate);
+    evaluate_until(&tabular, &policy, discount_rate, &stopper)
+        .map(|(state_values, num_sweeps)| (tabular.to_state_values(&state_values), num_sweeps))
+        .map_err(|error| error.map(|state_values| tabular.to_state_values(&state_values)))
+}
 
-    let mut state_values = state_values_prev.clone();
+fn evaluate_until<S, A>(
+    tabular: &TabularMDP<S, A>,
+    policy: &[Vec<(usize, Probability)>],
+    discount_rate: f64,
+    stopper: &Stopper,
+) -> Result<(Vec<f64>, usize), ConvergenceError<Vec<f64>>>
+where
+    S: Copy + Hash + Eq,
+    A: Copy + Hash + Eq,
+{
+    let mut state_values_prev = vec![0.0; tabular.num_states()];
 
     let mut num_iterations = 0;
 
     loop {
-        for &state in mdp.get_states() {
-            let action = policy.get_action(&state);
-            let transitions = mdp.transition(state, action);
-            let state_value = transitions
-                .iter()
-                .map(|&(next_state, prob)| {
-                    let reward = mdp.reward(state, action, next_state);
-                    let next_state_value = state_values_prev.get(&next_state).unwrap_or(&0.0);
-                    prob * (reward + discount_rate * next_state_value)
-                })
-                .sum();
-            state_values.insert(state.clone(), state_value);
-        }
+        let state_values = tabular.policy_values(policy, &state_values_prev, discount_rate);
 
         num_iterations += 1;
 
-        let max_diff = mdp
-            .get_states()
-            .iter()
-            .map(|s| (state_values[s] - state_values_prev[s]).abs())
-            .fold(f64::NEG_INFINITY, f64::max);
+        let max_diff = max_difference(&state_values, &state_values_prev);
 
-        if max_diff < threshold {
-            println!("  {}", num_iterations);
-            return (state_values, num_iterations);
+        let status = stopper.status(num_iterations, max_diff);
+        if !status.is_running() {
+            return status
+                .into_result(num_iterations, state_values)
+                .map(|state_values| (state_values, num_iterations));
         } else {
-            (state_values_prev, state_values) = (state_values, state_values_prev);
+            state_values_prev = state_values;
         }
     }
 }
 
-fn improve_policy<M>(
+/// Evaluates a policy exactly by solving the linear Bellman system
+/// (I - γP_π)V = R_π, where P_π and R_π are the transition matrix and
+/// expected one-step rewards under the policy. Fails if the system is
+/// singular, e.g. with no discounting and a policy that never terminates.
+pub fn evaluate_policy_exact<M, P>(
     mdp: &M,
-    state_values: HashMap<M::State, f64>,
+    policy: &P,
     discount_rate: f64,
-) -> HashMap<M::State, M::Action>
+) -> Result<HashMap<M::State, f64>, String>
 where
     M: MDP,
+    P: StochasticPolicy<M::State, M::Action>,
+{
+    let tabular = TabularMDP::from_mdp(mdp);
+    let policy = tabular.policy_rows(policy);
+    let state_values = solve_policy(&tabular, &policy, discount_rate)?;
+    Ok(tabular.to_state_values(&state_values))
+}
+
+fn solve_policy<S, A>(
+    tabular: &TabularMDP<S, A>,
+    policy: &[Vec<(usize, Probability)>],
+    discount_rate: f64,
+) -> Result<Vec<f64>, String>
+where
+    S: Copy + Hash + Eq,
+    A: Copy + Hash + Eq,
+{
+    let (transitions, rewards) = policy_matrices(tabular, policy);
+    let n = tabular.num_states();
+    let a = Array2::<f64>::eye(n) - discount_rate * transitions;
+    Ok(linalg::solve(a, &rewards)?.to_vec())
+}
+
+/// P_π and R_π, indexed like the states of `tabular`.
+fn policy_matrices<S, A>(
+    tabular: &TabularMDP<S, A>,
+    policy: &[Vec<(usize, Probability)>],
+) -> (Array2<f64>, Array1<f64>)
+where
+    S: Copy + Hash + Eq,
+    A: Copy + Hash + Eq,
+{
+    let n = tabular.num_states();
+    let mut transitions = Array2::zeros((n, n));
+    let mut rewards = Array1::zeros(n);
+    for (i, rows) in policy.iter().enumerate() {
+        for &(row, action_prob) in rows {
+            rewards[i] += action_prob * tabular.expected_reward(row);
+            let (next_states, probs) = tabular.successors(row);
+            for (&j, &prob) in next_states.iter().zip(probs) {
+                transitions[[i, j]] += action_prob * prob;
+            }
+        }
+    }
+
+    (transitions, rewards)
+}
+
+/// The largest change of any state's value, the Bellman residual of a sweep.
+pub(crate) fn max_difference(state_values: &[f64], state_values_prev: &[f64]) -> f64 {
+    state_values
+        .iter()
+        .zip(state_values_prev)
+        .map(|(v, v_prev)| (v - v_prev).abs())
+        .fold(0.0, f64::max)
+}
+
+/// How policy iteration evaluates each intermediate policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Evaluation {
+    /// Sweep until values change by less than the threshold
+    Iterative,
+    /// Solve the linear Bellman system directly
+    Exact,
+}
+
+/// How policy improvement chooses among actions whose values are within
+/// the tolerance of the best.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TieBreak {
+    /// The first tied action in the order of `available_actions`
+    First,
+    /// A tied action drawn with a generator seeded by `seed`
+    Random { seed: u64 },
+    /// Every tied action with equal probability. This uniform policy is
+    /// returned as [`Solution::tied_policy`], while `policy` takes the first
+    /// of the tied actions, which is as good once the iteration has converged.
+    All,
+}
+
+/// How policy iteration improves a policy. An action only replaces the
+/// incumbent if its value exceeds the incumbent's by more than `tolerance`,
+/// so equally good actions never make the policy oscillate. Policy
+/// iteration widens that margin by the error iterative evaluation may leave
+/// in the values, see [`policy_iteration_with`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Improvement {
+    pub tolerance: f64,
+    pub tie_break: TieBreak,
+}
+
+impl Default for Improvement {
+    fn default() -> Self {
+        Self {
+            tolerance: 1e-9,
+            tie_break: TieBreak::First,
+        }
+    }
+}
+
+impl Improvement {
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn with_tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.tie_break = tie_break;
+        self
+    }
+
+    /// The generator that breaks ties, seeded for [`TieBreak::Random`]
+    pub(crate) fn tie_rng(&self) -> StdRng {
+        match self.tie_break {
+            TieBreak::Random { seed } => StdRng::seed_from_u64(seed),
+            _ => StdRng::seed_from_u64(0),
+        }
+    }
+}
+
+/// The rows to take in every state after improving on `incumbent`, and the
+/// improved values. The incumbent rows are kept while all of them are within
+/// the tolerance plus `q_error` of the best; otherwise the new rows are
+/// chosen among those within the tolerance. If every Q is within `q_error`
+/// of the true Q of the incumbent policy, every change therefore strictly
+/// improves it.
+pub(crate) fn improve<S, A>(
+    tabular: &TabularMDP<S, A>,
+    q: &[f64],
+    incumbent: &[Vec<usize>],
+    improvement: &Improvement,
+    q_error: f64,
+    rng: &mut StdRng,
+) -> (Vec<Vec<usize>>, Vec<f64>)
+where
+    S: Copy + Hash + Eq,
+    A: Copy + Hash + Eq,
 {
-    let mut state_action_values = HashMap::new();
-
-    for (&state, &action) in mdp.state_actions() {
-        let action_values = &mut state_action_values.entry(state).or_insert(HashMap::new());
-        for &(next_state, prob) in mdp.transition(state, action) {
-            let reward = mdp.reward(state, action, next_state);
-            let action_value = action_values.entry(action).or_insert(0.0);
-            let next_value = state_values.get(&next_state).unwrap_or(&0.0);
-            *action_value += prob * (reward + discount_rate * next_value);
-        }
-    }
-
-    let actions = mdp.get_actions();
-    state_action_values
-        .into_iter()
-        .map(|(state, action_values)| {
-            let best_action = action_values
-                .into_iter()
-                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-                .unwrap_or((*actions.first().unwrap(), 0.0))
-                .0;
-            (state.clone(), best_action.clone())
+    (0..tabular.num_states())
+        .map(|s| {
+            let (near, best) = tabular.near_greedy(s, q, improvement.tolerance);
+            let margin = improvement.tolerance + 2.0 * q_error;
+            let rows = if !incumbent[s].is_empty()
+                && incumbent[s].iter().all(|&row| q[row] >= best - margin)
+            {
+                incumbent[s].clone()
+            } else {
+                match improvement.tie_break {
+                    TieBreak::First => near[..near.len().min(1)].to_vec(),
+                    TieBreak::Random { .. } => near.choose(rng).into_iter().copied().collect(),
+                    TieBreak::All => near,
+                }
+            };
+            (rows, best)
         })
-        .collect()
+        .unzip()
+}
+
+pub fn policy_iteration<M, R>(
+    mdp: &M,
+    discount_rate: f64,
+    stopping: impl Into<StoppingCriteria>,
+    rng: &mut R,
+) -> SolverResult<M>
+where
+    M: MDP,
+    R: Rng + ?Sized,
+{
+    policy_iteration_with(
+        mdp,
+        discount_rate,
+        stopping,
+        Evaluation::Iterative,
+        Improvement::default(),
+        rng,
+    )
 }
 
-pub fn policy_iteration<M>(
+/// Policy iteration with a choice of policy evaluation and improvement. The
+/// tolerance in `stopping` only applies to [`Evaluation::Iterative`]; the
+/// iteration budget caps both improvement steps and the sweeps of each
+/// evaluation. `rng` draws the initial policy.
+///
+/// Iterative evaluation stops with values up to threshold·γ/(1 - γ) away
+/// from the policy's, so each Q may be off by γ times that. An action must
+/// beat the incumbent by that error on both sides on top of the improvement
+/// tolerance, which makes every change a strict improvement and guarantees
+/// termination. Without discounting there is no such bound, and only the
+/// iteration budget in `stopping` keeps a noisy evaluation from cycling.
+pub fn policy_iteration_with<M, R>(
     mdp: &M,
     discount_rate: f64,
-    threshold: f64,
-    rng: &mut ThreadRng,
-) -> MDPPolicy<M>
+    stopping: impl Into<StoppingCriteria>,
+    evaluation: Evaluation,
+    improvement: Improvement,
+    rng: &mut R,
+) -> SolverResult<M>
 where
     M: MDP,
+    R: Rng + ?Sized,
 {
-    let actions = mdp.get_actions();
-    let states = mdp.get_states();
+    let tabular = TabularMDP::from_mdp(mdp);
+    let stopper = stopping.into().start(discount_rate);
+    let q_error = match evaluation {
+        Evaluation::Iterative if discount_rate < 1.0 => {
+            discount_rate * stopper.threshold() * discount_rate / (1.0 - discount_rate)
+        }
+        _ => 0.0,
+    };
+    let mut tie_rng = improvement.tie_rng();
 
     // random policy
-    let mut state_actions: HashMap<M::State, M::Action> = states
-        .into_iter()
-        .map(|state| {
-            let action = actions.choose(rng).expect("at least one action").clone();
-            (state.clone(), action)
-        })
+    let mut policy_rows: Vec<Vec<usize>> = (0..tabular.num_states())
+        .map(|s| tabular.rows(s).choose(rng).into_iter().collect())
         .collect();
+    let mut state_values = vec![0.0; tabular.num_states()];
 
     let mut num_iterations = 0;
+    let mut num_sweeps = 0;
+    let mut residuals = vec![];
     loop {
         num_iterations += 1;
 
-        let policy = MDPPolicy::new(state_actions.clone());
+        let policy = uniform(&policy_rows);
 
-        let (state_values, _) = evaluate_policy(mdp, &policy, discount_rate, threshold);
-        let new_state_actions = improve_policy(mdp, state_values, discount_rate);
+        let evaluated = match evaluation {
+            Evaluation::Iterative => evaluate_until(&tabular, &policy, discount_rate, &stopper)
+                .map_err(|error| (error.reason, *error.last_iterate)),
+            Evaluation::Exact => solve_policy(&tabular, &policy, discount_rate)
+                .map(|state_values| (state_values, 0))
+                .map_err(|message| (StopReason::Failed(message), state_values.clone())),
+        };
+        let (reason, evaluation_sweeps) = match evaluated {
+            Ok((values, evaluation_sweeps)) => {
+                state_values = values;
+                (None, evaluation_sweeps)
+            }
+            Err((reason, values)) => {
+                state_values = values;
+                (Some(reason), 0)
+            }
+        };
+        let action_values = tabular.q_values(&state_values, discount_rate);
+        num_sweeps += evaluation_sweeps + 1;
+        let (new_policy_rows, improved_values) = improve(
+            &tabular,
+            &action_values,
+            &policy_rows,
+            &improvement,
+            q_error,
+            &mut tie_rng,
+        );
+
+        // how far the evaluated policy is from satisfying the Bellman optimality equation
+        residuals.push(max_difference(&improved_values, &state_values));
 
-        if state_actions == new_state_actions {
-            println!("num iterations: {}", num_iterations);
-            return policy;
+        let reason = reason.or_else(|| {
+            if policy_rows == new_policy_rows {
+                None
+            } else {
+                stopper.exhausted(num_iterations)
+            }
+        });
+        if reason.is_some() || policy_rows == new_policy_rows {
+            let first_rows: Vec<Option<usize>> = policy_rows
+                .iter()
+                .map(|rows| rows.first().copied())
+                .collect();
+            let solution = Solution {
+                policy: tabular.to_policy(&first_rows),
+                tied_policy: (improvement.tie_break == TieBreak::All)
+                    .then(|| tabular.to_stochastic_policy(&policy)),
+                state_values: tabular.to_state_values(&state_values),
+                action_values: tabular.to_action_values(&action_values),
+                expected_return: tabular.expected_return(&state_values),
+                num_iterations,
+                num_sweeps,
+                num_backups: num_sweeps * tabular.num_states(),
+                residuals,
+            };
+            return match reason {
+                None => Ok(solution),
+                Some(reason) => Err(ConvergenceError {
+                    reason,
+                    num_iterations,
+                    last_iterate: Box::new(solution),
+                }),
+            };
         } else {
-            state_actions = new_state_actions;
+            policy_rows = new_policy_rows;
         }
     }
 }
 
-pub fn value_iteration<M>(mdp: &M, discount_rate: f64, threshold: f64) -> MDPPolicy<M>
+pub fn value_iteration<M>(
+    mdp: &M,
+    discount_rate: f64,
+    stopping: impl Into<StoppingCriteria>,
+) -> SolverResult<M>
 where
     M: MDP,
 {
-    let actions = mdp.get_actions();
+    let tabular = TabularMDP::from_mdp(mdp);
+    iterate_values(&tabular, discount_rate, stopping.into(), |values| {
+        tabular.q_values(values, discount_rate)
+    })
+}
 
-    let mut state_values_prev: HashMap<M::State, f64> = mdp
-        .get_states()
-        .into_iter()
-        .map(|s| (s.clone(), 0.0))
-        .collect();
+/// Synchronous value iteration where `q_values` gives the Q of every row of
+/// `tabular` from the previous state values, so solvers that only differ in
+/// how a row is backed up share the loop.
+pub(crate) fn iterate_values<M>(
+    tabular: &TabularMDP<M::State, M::Action>,
+    discount_rate: f64,
+    stopping: StoppingCriteria,
+    q_values: impl Fn(&[f64]) -> Vec<f64>,
+) -> SolverResult<M>
+where
+    M: MDP,
+{
+    let stopper = stopping.start(discount_rate);
+    let mut state_values_prev = vec![0.0; tabular.num_states()];
 
     let mut num_iterations = 0;
-
-    let mut state_action_values: HashMap<M::State, HashMap<M::Action, f64>>;
+    let mut residuals = vec![];
 
     loop {
         num_iterations += 1;
-        state_action_values = HashMap::new();
-
-        for (&state, &action) in mdp.state_actions() {
-            let action_values = &mut state_action_values
-                .entry(state.clone())
-                .or_insert(HashMap::new());
-            for &(next_state, prob) in mdp.transition(state, action) {
-                let reward = mdp.reward(state, action, next_state);
-                let action_value = action_values.entry(action.clone()).or_insert(0.0);
-                let next_value = state_values_prev.get(&next_state).unwrap_or(&0.0);
-                *action_value += prob * (reward + discount_rate * next_value);
-            }
-        }
 
-        let state_values: HashMap<M::State, f64> = state_action_values
-            .iter()
-            .map(|(state, action_values)| {
-                let max_value = if action_values.len() > 0 {
-                    action_values
-                        .values()
-                        .cloned()
-                        .fold(f64::NEG_INFINITY, f64::max)
-                } else {
-                    0.0
-                };
-                (state.clone(), max_value)
-            })
-            .collect();
+        let action_values = q_values(&state_values_prev);
+        let (policy_rows, state_values) = tabular.greedy(&action_values);
 
-        let max_diff = mdp
-            .get_states()
-            .iter()
-            .map(|s| (state_values[s] - state_values_prev[s]).abs())
-            .fold(f64::NEG_INFINITY, f64::max);
+        let max_diff = max_difference(&state_values, &state_values_prev);
+        residuals.push(max_diff);
 
-        if max_diff < threshold {
-            println!("num_iterations: {}", num_iterations);
-            break;
+        let status = stopper.status(num_iterations, max_diff);
+        if !status.is_running() {
+            let solution = Solution {
+                policy: tabular.to_policy(&policy_rows),
+                tied_policy: None,
+                state_values: tabular.to_state_values(&state_values),
+                action_values: tabular.to_action_values(&action_values),
+                expected_return: tabular.expected_return(&state_values),
+                num_iterations,
+                num_sweeps: num_iterations,
+                num_backups: num_iterations * tabular.num_states(),
+                residuals,
+            };
+            return status.into_result(num_iterations, solution);
         } else {
             state_values_prev = state_values;
         }
     }
+}
+
+/// Policy iteration that only runs `num_evaluation_sweeps` sweeps of policy
+/// evaluation between improvements, warm-starting each evaluation from the
+/// previous values. One sweep is value iteration; many approach policy iteration.
+pub fn modified_policy_iteration<M>(
+    mdp: &M,
+    discount_rate: f64,
+    stopping: impl Into<StoppingCriteria>,
+    num_evaluation_sweeps: usize,
+) -> SolverResult<M>
+where
+    M: MDP,
+{
+    let tabular = TabularMDP::from_mdp(mdp);
+    let stopper = stopping.into().start(discount_rate);
+    let mut state_values = vec![0.0; tabular.num_states()];
+
+    let mut num_iterations = 0;
+    let mut num_sweeps = 0;
+    let mut residuals = vec![];
+
+    loop {
+        num_iterations += 1;
+
+        // improvement doubles as the first evaluation sweep of the greedy policy
+        let action_values = tabular.q_values(&state_values, discount_rate);
+        let (policy_rows, improved_values) = tabular.greedy(&action_values);
+        num_sweeps += 1;
+
+        let max_diff = max_difference(&improved_values, &state_values);
+        residuals.push(max_diff);
+
+        let status = stopper.status(num_iterations, max_diff);
+        if !status.is_running() {
+            let solution = Solution {
+                policy: tabular.to_policy(&policy_rows),
+                tied_policy: None,
+                state_values: tabular.to_state_values(&improved_values),
+                action_values: tabular.to_action_values(&action_values),
+                expected_return: tabular.expected_return(&improved_values),
+                num_iterations,
+                num_sweeps,
+                num_backups: num_sweeps * tabular.num_states(),
+                residuals,
+            };
+            return status.into_result(num_iterations, solution);
+        }
+
+        state_values = improved_values;
+        let policy = deterministic(&policy_rows);
+        for _ in 1..num_evaluation_sweeps {
+            state_values = tabular.policy_values(&policy, &state_values, discount_rate);
+            num_sweeps += 1;
+        }
+    }
+}
+
+/// Gauss-Seidel value iteration: each backup immediately uses the values
+/// already updated earlier in the same sweep.
+pub fn value_iteration_in_place<M>(
+    mdp: &M,
+    discount_rate: f64,
+    stopping: impl Into<StoppingCriteria>,
+) -> SolverResult<M>
+where
+    M: MDP,
+{
+    value_iteration_ordered(mdp, discount_rate, stopping, mdp.get_states())
+}
 
-    let state_actions: HashMap<M::State, M::Action> = state_action_values
+/// In-place value iteration that backs states up in the given `order`.
+/// Ordering states so that successors come before their predecessors (e.g.
+/// backwards from the goal) lets value propagate in fewer sweeps.
+/// States missing from `order` are never backed up and keep a value of zero.
+pub fn value_iteration_ordered<M>(
+    mdp: &M,
+    discount_rate: f64,
+    stopping: impl Into<StoppingCriteria>,
+    order: &[M::State],
+) -> SolverResult<M>
+where
+    M: MDP,
+{
+    let tabular = TabularMDP::from_mdp(mdp);
+    let order: Vec<usize> = order
         .iter()
-        .map(|(state, action_values)| {
-            let best_action = action_values
-                .into_iter()
-                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-                .unwrap_or((actions.first().unwrap(), &0.0))
-                .0;
-            (state.clone(), best_action.clone())
-        })
+        .filter_map(|state| tabular.state_index(state))
         .collect();
+    let stopper = stopping.into().start(discount_rate);
+    let mut state_values = vec![0.0; tabular.num_states()];
+
+    let mut num_iterations = 0;
+    let mut residuals = vec![];
+
+    loop {
+        num_iterations += 1;
+
+        let mut max_diff: f64 = 0.0;
+        for &s in &order {
+            let value = tabular.max_q_value(s, &state_values, discount_rate);
+            max_diff = max_diff.max((value - state_values[s]).abs());
+            state_values[s] = value;
+        }
+        residuals.push(max_diff);
+
+        let status = stopper.status(num_iterations, max_diff);
+        if !status.is_running() {
+            let action_values = tabular.q_values(&state_values, discount_rate);
+            let (policy_rows, _) = tabular.greedy(&action_values);
+            let solution = Solution {
+                policy: tabular.to_policy(&policy_rows),
+                tied_policy: None,
+                state_values: tabular.to_state_values(&state_values),
+                action_values: tabular.to_action_values(&action_values),
+                expected_return: tabular.expected_return(&state_values),
+                num_iterations,
+                num_sweeps: num_iterations,
+                num_backups: num_iterations * order.len(),
+                residuals,
+            };
+            return status.into_result(num_iterations, solution);
+        }
+    }
+}
 
-    MDPPolicy::new(state_actions)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        direction::Direction,
+        environment::Reward,
+        grid_world::{GridWorld, GridWorldMDP, FROZEN_LAKE_4X4, FROZEN_LAKE_8X8},
+        mdp::Probability,
+        policy::{MDPPolicy, Policy, StochasticMDPPolicy},
+        stopping::DEFAULT_MAX_ITERATIONS,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::time::Duration;
+
+    /// A single state that costs 1 per step and can never be left.
+    struct Treadmill;
+
+    impl MDP for Treadmill {
+        type State = ();
+        type Action = ();
+
+        fn get_states(&self) -> &[()] {
+            &[()]
+        }
+        fn get_actions(&self) -> &[()] {
+            &[()]
+        }
+        fn transition(&self, _state: (), _action: ()) -> &[((), Probability)] {
+            &[((), 1.0)]
+        }
+        fn reward(&self, _state: (), _action: (), _next_state: ()) -> Reward {
+            -1.0
+        }
+    }
+
+    static ORDERS: [u8; 4] = [0, 1, 2, 3];
+
+    /// Stock of 0 to 3 units. Up to `3 - stock` units can be ordered at a
+    /// cost of 1 each, then 0, 1 or 2 units are demanded with equal
+    /// probability and sell for 2 each while stock lasts.
+    struct Inventory {
+        transitions: HashMap<(u8, u8), Vec<(u8, Probability)>>,
+    }
+
+    impl Inventory {
+        fn new() -> Self {
+            let mut transitions = HashMap::new();
+            for stock in 0..=3u8 {
+                for order in 0..=3 - stock {
+                    let mut next_stocks: Vec<(u8, Probability)> = vec![];
+                    for demand in 0..=2 {
+                        let next_stock = (stock + order).saturating_sub(demand);
+                        match next_stocks.iter_mut().find(|(s, _)| *s == next_stock) {
+                            Some((_, p)) => *p += 1.0 / 3.0,
+                            None => next_stocks.push((next_stock, 1.0 / 3.0)),
+                        }
+                    }
+                    transitions.insert((stock, order), next_stocks);
+                }
+            }
+            Self { transitions }
+        }
+    }
+
+    impl MDP for Inventory {
+        type State = u8;
+        type Action = u8;
+
+        fn get_states(&self) -> &[u8] {
+            &ORDERS
+        }
+        fn get_actions(&self) -> &[u8] {
+            &ORDERS
+        }
+        fn available_actions(&self, stock: u8) -> &[u8] {
+            &ORDERS[..=(3 - stock) as usize]
+        }
+        fn transition(&self, stock: u8, order: u8) -> &[(u8, Probability)] {
+            &self.transitions[&(stock, order)]
+        }
+        fn reward(&self, stock: u8, order: u8, next_stock: u8) -> Reward {
+            2.0 * (stock + order - next_stock) as f64 - order as f64
+        }
+    }
+
+    fn assert_same_values(
+        mdp: &GridWorldMDP,
+        a: &Solution<GridWorldMDP>,
+        b: &Solution<GridWorldMDP>,
+    ) {
+        for state in mdp.get_states() {
+            assert!((a.state_value(state) - b.state_value(state)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn value_iteration_solution() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 0.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let solution = value_iteration(&mdp, 0.9, 1e-8).unwrap();
+
+        assert_eq!(solution.residuals.len(), solution.num_iterations);
+        assert!(solution.final_residual().unwrap() < 1e-8);
+
+        // one step from the goal
+        assert!((solution.state_value(&14) - 1.0).abs() < 1e-6);
+        assert!((solution.action_value(&14, &Direction::Right) - 1.0).abs() < 1e-6);
+        // terminal states are worth nothing
+        assert_eq!(solution.state_value(&15), 0.0);
+        // start state is six steps from the goal
+        assert!((solution.state_value(&0) - 0.9f64.powi(5)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn policy_and_value_iteration_agree() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 2.0 / 3.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let mut rng = StdRng::seed_from_u64(0);
+        let pi = policy_iteration(&mdp, 0.9, 1e-10, &mut rng).unwrap();
+        let vi = value_iteration(&mdp, 0.9, 1e-10).unwrap();
+
+        assert_eq!(pi.residuals.len(), pi.num_iterations);
+        for state in mdp.get_states() {
+            assert!((pi.state_value(state) - vi.state_value(state)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn seeded_policy_iteration_is_reproducible() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 2.0 / 3.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let first = policy_iteration(&mdp, 0.9, 1e-8, &mut StdRng::seed_from_u64(3)).unwrap();
+        let second = policy_iteration(&mdp, 0.9, 1e-8, &mut StdRng::seed_from_u64(3)).unwrap();
+
+        assert_eq!(first.num_iterations, second.num_iterations);
+        assert_eq!(first.residuals, second.residuals);
+        for &state in mdp.get_states() {
+            if !mdp.is_terminal(state) {
+                assert_eq!(
+                    first.policy.get_action(&state),
+                    second.policy.get_action(&state)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tie_breaks_converge_on_tied_actions() {
+        // without noise, many cells have several equally short paths to the goal
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 0.0, 0.99).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let vi = value_iteration(&mdp, 0.9, 1e-10).unwrap();
+        for tie_break in [TieBreak::First, TieBreak::Random { seed: 5 }, TieBreak::All] {
+            let improvement = Improvement::default().with_tie_break(tie_break);
+            let solve = || {
+                let mut rng = StdRng::seed_from_u64(1);
+                policy_iteration_with(
+                    &mdp,
+                    0.9,
+                    1e-10,
+                    Evaluation::Iterative,
+                    improvement,
+                    &mut rng,
+                )
+                .unwrap()
+            };
+            let (first, second) = (solve(), solve());
+            assert_same_values(&mdp, &vi, &first);
+            assert_eq!(first.num_iterations, second.num_iterations);
+            assert_eq!(first.tied_policy.is_some(), tie_break == TieBreak::All);
+            if let Some(tied_policy) = &first.tied_policy {
+                let mut num_tied = 0;
+                for &state in mdp.get_states() {
+                    if mdp.is_terminal(state) {
+                        continue;
+                    }
+                    let action_probs = tied_policy.action_probs(&state);
+                    assert_eq!(action_probs[0].0, first.policy.get_action(&state));
+                    num_tied += (action_probs.len() > 1) as usize;
+                }
+                assert!(num_tied > 0);
+            }
+            for &state in mdp.get_states() {
+                if !mdp.is_terminal(state) {
+                    assert_eq!(
+                        first.policy.get_action(&state),
+                        second.policy.get_action(&state)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn improvement_keeps_equally_good_incumbents() {
+        let tabular = TabularMDP::from_mdp(&Inventory::new());
+        let q = vec![1.0; tabular.num_rows()];
+        let incumbent: Vec<Vec<usize>> = (0..tabular.num_states())
+            .map(|s| vec![tabular.rows(s).end - 1])
+            .collect();
+        let mut rng = StdRng::seed_from_u64(0);
+        for tie_break in [TieBreak::First, TieBreak::Random { seed: 0 }, TieBreak::All] {
+            let improvement = Improvement::default().with_tie_break(tie_break);
+            let (rows, _) = improve(&tabular, &q, &incumbent, &improvement, 0.0, &mut rng);
+            assert_eq!(rows, incumbent);
+        }
+
+        // a NaN never wins, and a gain within the tolerance is not worth a change
+        let mut q = q;
+        q[0] = f64::NAN;
+        q[1] = 1.0 + 1e-12;
+        let improvement = Improvement::default().with_tie_break(TieBreak::All);
+        let (rows, values) = improve(&tabular, &q, &incumbent, &improvement, 0.0, &mut rng);
+        assert_eq!(rows, incumbent);
+        assert_eq!(values[0], 1.0 + 1e-12);
+        let mut incumbent = incumbent;
+        incumbent[0] = vec![0];
+        let (rows, _) = improve(&tabular, &q, &incumbent, &improvement, 0.0, &mut rng);
+        assert_eq!(rows[0], (1..tabular.rows(0).end).collect::<Vec<_>>());
+
+        // a lead smaller than the evaluation error may just be noise
+        let mut q = vec![1.0; tabular.num_rows()];
+        let incumbent: Vec<Vec<usize>> = (0..tabular.num_states())
+            .map(|s| vec![tabular.rows(s).start])
+            .collect();
+        q[1] = 1.0 + 1e-6;
+        let improvement = Improvement::default();
+        let (rows, _) = improve(&tabular, &q, &incumbent, &improvement, 1e-6, &mut rng);
+        assert_eq!(rows, incumbent);
+        let (rows, _) = improve(&tabular, &q, &incumbent, &improvement, 1e-7, &mut rng);
+        assert_eq!(rows[0], vec![1]);
+    }
+
+    #[test]
+    fn modified_policy_iteration_needs_fewer_sweeps() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 2.0 / 3.0, 0.99).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let mut rng = StdRng::seed_from_u64(0);
+        let pi = policy_iteration(&mdp, 0.99, 1e-8, &mut rng).unwrap();
+        let mpi = modified_policy_iteration(&mdp, 0.99, 1e-8, 10).unwrap();
+
+        assert_same_values(&mdp, &pi, &mpi);
+        assert!(mpi.num_sweeps < pi.num_sweeps / 2);
+    }
+
+    #[test]
+    fn in_place_value_iteration_needs_fewer_sweeps() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 2.0 / 3.0, 0.99).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let vi = value_iteration(&mdp, 0.99, 1e-8).unwrap();
+        let gauss_seidel = value_iteration_in_place(&mdp, 0.99, 1e-8).unwrap();
+
+        let reversed: Vec<usize> = mdp.get_states().iter().rev().copied().collect();
+        let ordered = value_iteration_ordered(&mdp, 0.99, 1e-8, &reversed).unwrap();
+
+        assert_same_values(&mdp, &vi, &gauss_seidel);
+        assert_same_values(&mdp, &vi, &ordered);
+        assert!(gauss_seidel.num_sweeps < vi.num_sweeps);
+        assert!(ordered.num_sweeps < gauss_seidel.num_sweeps);
+    }
+
+    #[test]
+    fn large_grid_world() {
+        let mut map = vec!["F".repeat(100); 100];
+        map[0].replace_range(0..1, "S");
+        map[99].replace_range(99..100, "G");
+        let map: Vec<&str> = map.iter().map(String::as_str).collect();
+        let mdp = GridWorldMDP::new(GridWorld::from_map(&map, 0.0, 0.99).unwrap());
+
+        let solution = value_iteration_in_place(&mdp, 0.99, 1e-8).unwrap();
+        // the goal is 198 steps from the start
+        assert!((solution.state_value(&0) - 0.99f64.powi(197)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn state_dependent_actions() {
+        // solvers would panic looking up a transition for an unavailable order
+        let mdp = Inventory::new();
+        assert!(crate::validation::validate(&mdp).is_valid());
+
+        let vi = value_iteration(&mdp, 0.9, 1e-10).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let solutions = [
+            policy_iteration(&mdp, 0.9, 1e-10, &mut rng).unwrap(),
+            policy_iteration_with(
+                &mdp,
+                0.9,
+                1e-10,
+                Evaluation::Exact,
+                Improvement::default(),
+                &mut rng,
+            )
+            .unwrap(),
+            modified_policy_iteration(&mdp, 0.9, 1e-10, 5).unwrap(),
+            value_iteration_in_place(&mdp, 0.9, 1e-10).unwrap(),
+            crate::prioritized_sweeping::prioritized_sweeping(&mdp, 0.9, 1e-10).unwrap(),
+        ];
+        let lp = crate::linear_programming::solve(&mdp, 0.9).unwrap();
+
+        for &stock in mdp.get_states() {
+            let available = mdp.available_actions(stock);
+            assert_eq!(vi.action_values[&stock].len(), available.len());
+            assert!(available.contains(&vi.policy.get_action(&stock)));
+            assert!(available.contains(&lp.policy.get_action(&stock)));
+            assert!((lp.state_values[&stock] - vi.state_value(&stock)).abs() < 1e-6);
+            for solution in &solutions {
+                assert!(available.contains(&solution.policy.get_action(&stock)));
+                assert!((solution.state_value(&stock) - vi.state_value(&stock)).abs() < 1e-6);
+            }
+        }
+
+        // random actions are drawn from what is available
+        let uniform = StochasticMDPPolicy::uniform(&mdp);
+        let (values, _) = evaluate_policy(&mdp, &uniform, 0.9, 1e-10).unwrap();
+        let exact = evaluate_policy_exact(&mdp, &uniform, 0.9).unwrap();
+        for stock in mdp.get_states() {
+            assert!((values[stock] - exact[stock]).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn exact_evaluation_matches_iterative() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 2.0 / 3.0, 0.99).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let policy = value_iteration(&mdp, 0.99, 1e-8).unwrap().policy;
+        let (iterative, _) = evaluate_policy(&mdp, &policy, 0.99, 1e-12).unwrap();
+        let exact = evaluate_policy_exact(&mdp, &policy, 0.99).unwrap();
+
+        for state in mdp.get_states() {
+            assert!((iterative[state] - exact[state]).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn exact_policy_iteration() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 2.0 / 3.0, 0.99).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let mut rng = StdRng::seed_from_u64(0);
+        let exact = policy_iteration_with(
+            &mdp,
+            0.99,
+            1e-8,
+            Evaluation::Exact,
+            Improvement::default(),
+            &mut rng,
+        )
+        .unwrap();
+        let vi = value_iteration(&mdp, 0.99, 1e-10).unwrap();
+
+        assert_same_values(&mdp, &exact, &vi);
+        assert_eq!(exact.num_sweeps, exact.num_iterations);
+        assert!(exact.final_residual().unwrap() < 1e-10);
+    }
+
+    #[test]
+    fn exact_evaluation_of_non_terminating_policy() {
+        // bumping into the top-left wall forever with no discounting
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 0.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let policy = MDPPolicy::<GridWorldMDP>::new(
+            mdp.get_states()
+                .iter()
+                .map(|&s| (s, Direction::Up))
+                .collect(),
+        );
+        assert!(evaluate_policy_exact(&mdp, &policy, 1.0).is_err());
+    }
+
+    #[test]
+    fn evaluate_stochastic_policies() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 0.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let uniform = StochasticMDPPolicy::uniform(&mdp);
+        let (iterative, _) = evaluate_policy(&mdp, &uniform, 0.9, 1e-12).unwrap();
+        let exact = evaluate_policy_exact(&mdp, &uniform, 0.9).unwrap();
+        for state in mdp.get_states() {
+            assert!((iterative[state] - exact[state]).abs() < 1e-9);
+        }
+
+        // next to the goal, a random move reaches it a quarter of the time
+        let one_step =
+            0.25 + 0.25 * 0.9 * exact[&10] + 0.25 * 0.9 * exact[&13] + 0.25 * 0.9 * exact[&14];
+        assert!((exact[&14] - one_step).abs() < 1e-9);
+
+        // exploring can only cost value, and not exploring changes nothing
+        let optimal = value_iteration(&mdp, 0.9, 1e-12).unwrap();
+        let greedy = StochasticMDPPolicy::epsilon_greedy(&mdp, &optimal.policy, 0.0);
+        let exploring = StochasticMDPPolicy::epsilon_greedy(&mdp, &optimal.policy, 0.3);
+        let greedy_values = evaluate_policy_exact(&mdp, &greedy, 0.9).unwrap();
+        let exploring_values = evaluate_policy_exact(&mdp, &exploring, 0.9).unwrap();
+        for state in mdp.get_states() {
+            assert!((greedy_values[state] - optimal.state_value(state)).abs() < 1e-9);
+            assert!(exploring_values[state] <= greedy_values[state] + 1e-12);
+        }
+        assert!(exploring_values[&0] < greedy_values[&0]);
+    }
+
+    #[test]
+    fn undiscounted_divergence_is_reported() {
+        let criteria = StoppingCriteria::residual(1e-6).with_max_iterations(50);
+
+        let error = value_iteration(&Treadmill, 1.0, criteria).err().unwrap();
+        assert_eq!(error.reason, StopReason::MaxIterations(50));
+        assert_eq!(error.num_iterations, 50);
+        assert_eq!(error.last_iterate.state_value(&()), -50.0);
+        assert_eq!(error.last_iterate.residuals.len(), 50);
+
+        let policy = MDPPolicy::<Treadmill>::new(HashMap::from([((), ())]));
+        let error = evaluate_policy(&Treadmill, &policy, 1.0, criteria)
+            .err()
+            .unwrap();
+        assert_eq!(error.last_iterate[&()], -50.0);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let error = policy_iteration(&Treadmill, 1.0, criteria, &mut rng)
+            .err()
+            .unwrap();
+        assert_eq!(error.reason, StopReason::MaxIterations(50));
+        let error = policy_iteration_with(
+            &Treadmill,
+            1.0,
+            criteria,
+            Evaluation::Exact,
+            Improvement::default(),
+            &mut rng,
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(error.reason, StopReason::Failed(_)));
+
+        // a bare threshold still cannot hang without discounting
+        let error = value_iteration(&Treadmill, 1.0, 1e-6).err().unwrap();
+        assert_eq!(
+            error.reason,
+            StopReason::MaxIterations(DEFAULT_MAX_ITERATIONS)
+        );
+    }
+
+    #[test]
+    fn time_budget() {
+        let criteria = StoppingCriteria::residual(1e-6).with_time_budget(Duration::from_millis(20));
+        let error = value_iteration_in_place(&Treadmill, 1.0, criteria)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.reason,
+            StopReason::TimeBudget(Duration::from_millis(20))
+        );
+        assert!(error.num_iterations > 0);
+    }
+
+    #[test]
+    fn epsilon_optimal_stopping() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 2.0 / 3.0, 0.99).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let epsilon = 1e-3;
+        let solution =
+            value_iteration(&mdp, 0.99, StoppingCriteria::epsilon_optimal(epsilon)).unwrap();
+        let optimal = value_iteration(&mdp, 0.99, 1e-12).unwrap();
+        let achieved = evaluate_policy_exact(&mdp, &solution.policy, 0.99).unwrap();
+
+        assert!(solution.final_residual().unwrap() < epsilon * 0.01 / 1.98);
+        for state in mdp.get_states() {
+            assert!(optimal.state_value(state) - achieved[state] < epsilon);
+        }
+    }
 }
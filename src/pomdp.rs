@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::mdp::{Probability, MDP};
+
+/// A belief over the hidden state, P(s), keyed by state. States missing
+/// from the map have probability zero.
+pub type Belief<S> = HashMap<S, Probability>;
+
+/// A partially observable MDP: the agent does not see the state it lands
+/// in, only an observation drawn from a distribution that depends on it.
+pub trait POMDP: MDP {
+    type Observation: Copy + Hash + Eq;
+
+    fn get_observations(&self) -> &[Self::Observation];
+
+    /// O(o | a, s'), the observations that may follow taking `action` and
+    /// landing in `next_state`, with probabilities summing to one
+    fn observation(
+        &self,
+        action: Self::Action,
+        next_state: Self::State,
+    ) -> &[(Self::Observation, Probability)];
+}
+
+/// The belief before any observation: the initial distribution.
+pub fn initial_belief<P: POMDP>(pomdp: &P) -> Belief<P::State> {
+    let mut belief = Belief::new();
+    for (state, prob) in pomdp.initial_distribution() {
+        if prob > 0.0 {
+            *belief.entry(state).or_insert(0.0) += prob;
+        }
+    }
+    belief
+}
+
+/// P(s' | b, a) = Σ_s T(s' | s, a) b(s), the belief after `action` before
+/// its observation arrives. Mass on states where `action` leads nowhere is lost.
+fn predict<P: POMDP>(pomdp: &P, belief: &Belief<P::State>, action: P::Action) -> Belief<P::State> {
+    let mut predicted = Belief::new();
+    for (&state, &prob) in belief {
+        if prob == 0.0 || !pomdp.available_actions(state).contains(&action) {
+            continue;
+        }
+        for &(next_state, next_prob) in pomdp.transition(state, action) {
+            *predicted.entry(next_state).or_insert(0.0) += prob * next_prob;
+        }
+    }
+    predicted
+}
+
+/// The belief after `action` and `observation`, weighted by the probability
+/// of the observation in each state, and the total weight.
+fn correct<P: POMDP>(
+    pomdp: &P,
+    predicted: Belief<P::State>,
+    action: P::Action,
+    observation: P::Observation,
+) -> (Belief<P::State>, Probability) {
+    let mut total = 0.0;
+    let weighted = predicted
+        .into_iter()
+        .filter_map(|(next_state, prob)| {
+            let likelihood: Probability = pomdp
+                .observation(action, next_state)
+                .iter()
+                .filter(|(o, _)| *o == observation)
+                .map(|&(_, p)| p)
+                .sum();
+            let weight = prob * likelihood;
+            total += weight;
+            (weight > 0.0).then_some((next_state, weight))
+        })
+        .collect();
+    (weighted, total)
+}
+
+/// P(o | b, a), how likely `observation` is after taking `action` in `belief`.
+pub fn observation_probability<P: POMDP>(
+    pomdp: &P,
+    belief: &Belief<P::State>,
+    action: P::Action,
+    observation: P::Observation,
+) -> Probability {
+    correct(pomdp, predict(pomdp, belief, action), action, observation).1
+}
+
+/// Bayes' rule, b'(s') ∝ O(o | a, s') Σ_s T(s' | s, a) b(s). Fails if the
+/// observation is impossible under the belief.
+pub fn update_belief<P: POMDP>(
+    pomdp: &P,
+    belief: &Belief<P::State>,
+    action: P::Action,
+    observation: P::Observation,
+) -> Result<Belief<P::State>, String> {
+    let (mut updated, total) = correct(pomdp, predict(pomdp, belief, action), action, observation);
+    if total <= 0.0 {
+        return Err("the observation is impossible under the belief".into());
+    }
+    for prob in updated.values_mut() {
+        *prob /= total;
+    }
+    Ok(updated)
+}
+
+/// Tracks the belief of an agent acting in a POMDP, one step at a time.
+pub struct BeliefFilter<'a, P: POMDP> {
+    pomdp: &'a P,
+    belief: Belief<P::State>,
+}
+
+impl<'a, P: POMDP> BeliefFilter<'a, P> {
+    pub fn new(pomdp: &'a P) -> Self {
+        Self {
+            pomdp,
+            belief: initial_belief(pomdp),
+        }
+    }
+
+    pub fn belief(&self) -> &Belief<P::State> {
+        &self.belief
+    }
+
+    /// Updates the belief with the observation that followed `action`. The
+    /// belief is unchanged if the observation is impossible.
+    pub fn update(
+        &mut self,
+        action: P::Action,
+        observation: P::Observation,
+    ) -> Result<&Belief<P::State>, String> {
+        self.belief = update_belief(self.pomdp, &self.belief, action, observation)?;
+        Ok(&self.belief)
+    }
+
+    pub fn reset(&mut self) -> &Belief<P::State> {
+        self.belief = initial_belief(self.pomdp);
+        &self.belief
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tiger::{Tiger, TigerAction, TigerObservation, TigerState};
+
+    #[test]
+    fn listening_to_the_tiger() {
+        let mut filter = BeliefFilter::new(&Tiger);
+        assert_eq!(filter.belief()[&TigerState::Left], 0.5);
+
+        let belief = filter
+            .update(TigerAction::Listen, TigerObservation::HearLeft)
+            .unwrap();
+        assert!((belief[&TigerState::Left] - 0.85).abs() < 1e-12);
+        let belief = filter
+            .update(TigerAction::Listen, TigerObservation::HearLeft)
+            .unwrap();
+        let expected = 0.85 * 0.85 / (0.85 * 0.85 + 0.15 * 0.15);
+        assert!((belief[&TigerState::Left] - expected).abs() < 1e-12);
+
+        let hear_right = observation_probability(
+            &Tiger,
+            filter.belief(),
+            TigerAction::Listen,
+            TigerObservation::HearRight,
+        );
+        assert!((hear_right - (expected * 0.15 + (1.0 - expected) * 0.85)).abs() < 1e-12);
+
+        // opening a door starts over with the tiger behind either one
+        let belief = filter
+            .update(TigerAction::OpenLeft, TigerObservation::HearRight)
+            .unwrap();
+        assert!((belief[&TigerState::Left] - 0.5).abs() < 1e-12);
+        assert_eq!(filter.reset().len(), 2);
+    }
+}
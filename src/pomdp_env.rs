@@ -0,0 +1,220 @@
+use std::fmt::{Debug, Display};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::environment::{Environment, SeedableEnvironment, StepResult};
+use crate::mdp_env::{sample, sample_initial_state, sample_transition};
+use crate::pomdp::{initial_belief, update_belief, Belief, POMDP};
+
+/// Runs episodes of a POMDP, showing the agent observations instead of
+/// states. The current "state" is the last observation, or `None` before
+/// the first step of an episode. Episodes start in a state drawn from the
+/// initial distribution and end in a terminal state.
+///
+/// The environment tracks the belief of an agent that knows the model, so
+/// that the actions it offers depend only on what the agent has seen.
+pub struct ObservationEnv<P: POMDP, R: Rng = StdRng> {
+    pub pomdp: P,
+    state: P::State,
+    observation: Option<P::Observation>,
+    belief: Belief<P::State>,
+    rng: R,
+}
+
+impl<P: POMDP, R: Rng> ObservationEnv<P, R> {
+    pub fn new(pomdp: P, mut rng: R) -> Self {
+        let state = sample_initial_state(&pomdp, &mut rng);
+        let belief = initial_belief(&pomdp);
+        Self {
+            pomdp,
+            state,
+            observation: None,
+            belief,
+            rng,
+        }
+    }
+
+    /// The state the agent cannot see, e.g. to check a belief against
+    pub fn hidden_state(&self) -> P::State {
+        self.state
+    }
+}
+
+impl<P, R> Environment for ObservationEnv<P, R>
+where
+    P: POMDP,
+    P::State: Debug,
+    P::Action: Debug + Display,
+    P::Observation: Debug,
+    R: Rng,
+{
+    type State = Option<P::Observation>;
+    type Action = P::Action;
+
+    fn current_state(&self) -> &Self::State {
+        &self.observation
+    }
+
+    /// The actions available in every non-terminal state the agent may be
+    /// in, given what it has observed, so they reveal nothing about the
+    /// hidden state. Empty once only terminal states remain.
+    fn available_actions(&self) -> Vec<Self::Action> {
+        let support: Vec<P::State> = self
+            .belief
+            .keys()
+            .copied()
+            .filter(|&state| !self.pomdp.is_terminal(state))
+            .collect();
+        if support.is_empty() {
+            return vec![];
+        }
+        self.pomdp
+            .get_actions()
+            .iter()
+            .filter(|action| {
+                support
+                    .iter()
+                    .all(|&state| self.pomdp.available_actions(state).contains(action))
+            })
+            .copied()
+            .collect()
+    }
+
+    fn step(&mut self, action: &Self::Action) -> Result<StepResult<Self::State>, String> {
+        if !self.available_actions().contains(action) {
+            return Err(format!("{} is not available", action));
+        }
+        let (next_state, reward) =
+            sample_transition(&self.pomdp, self.state, *action, &mut self.rng)?;
+        let observation = sample(self.pomdp.observation(*action, next_state), &mut self.rng)?;
+
+        self.state = next_state;
+        self.observation = Some(observation);
+        self.belief = update_belief(&self.pomdp, &self.belief, *action, observation)?;
+
+        Ok(StepResult::new(
+            self.observation,
+            reward,
+            self.pomdp.is_terminal(next_state),
+        ))
+    }
+
+    fn reset(&mut self) -> &Self::State {
+        self.state = sample_initial_state(&self.pomdp, &mut self.rng);
+        self.observation = None;
+        self.belief = initial_belief(&self.pomdp);
+        &self.observation
+    }
+}
+
+impl<P, R> SeedableEnvironment for ObservationEnv<P, R>
+where
+    P: POMDP,
+    P::State: Debug,
+    P::Action: Debug + Display,
+    P::Observation: Debug,
+    R: Rng + SeedableRng,
+{
+    fn seed(&mut self, seed: u64) {
+        self.rng = R::seed_from_u64(seed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::Reward;
+    use crate::mdp::{Probability, MDP};
+    use crate::pomdp::BeliefFilter;
+    use crate::tiger::{Tiger, TigerAction, TigerObservation, TigerState};
+
+    /// Two states, equally likely at first, that `'l'` reveals by showing the
+    /// state itself. Only state 0 allows `'x'`.
+    struct Locked;
+
+    impl MDP for Locked {
+        type State = u8;
+        type Action = char;
+
+        fn get_states(&self) -> &[u8] {
+            &[0, 1]
+        }
+        fn get_actions(&self) -> &[char] {
+            &['l', 'x']
+        }
+        fn available_actions(&self, state: u8) -> &[char] {
+            if state == 0 {
+                &['l', 'x']
+            } else {
+                &['l']
+            }
+        }
+        fn transition(&self, state: u8, _action: char) -> &[(u8, Probability)] {
+            if state == 0 {
+                &[(0, 1.0)]
+            } else {
+                &[(1, 1.0)]
+            }
+        }
+        fn reward(&self, _state: u8, _action: char, _next_state: u8) -> Reward {
+            0.0
+        }
+    }
+
+    impl POMDP for Locked {
+        type Observation = u8;
+
+        fn get_observations(&self) -> &[u8] {
+            &[0, 1]
+        }
+        fn observation(&self, _action: char, next_state: u8) -> &[(u8, Probability)] {
+            if next_state == 0 {
+                &[(0, 1.0)]
+            } else {
+                &[(1, 1.0)]
+            }
+        }
+    }
+
+    #[test]
+    fn actions_do_not_reveal_the_hidden_state() {
+        for seed in 0..10 {
+            let mut env = ObservationEnv::new(Locked, StdRng::seed_from_u64(seed));
+            assert_eq!(env.available_actions(), vec!['l']);
+            assert!(env.step(&'x').is_err());
+
+            env.step(&'l').unwrap();
+            let expected = Locked.available_actions(env.hidden_state()).to_vec();
+            assert_eq!(env.available_actions(), expected);
+
+            env.reset();
+            assert_eq!(env.available_actions(), vec!['l']);
+        }
+    }
+
+    #[test]
+    fn listening_tracks_the_tiger() {
+        let mut env = ObservationEnv::new(Tiger, StdRng::seed_from_u64(0));
+        let mut filter = BeliefFilter::new(&Tiger);
+        assert_eq!(*env.current_state(), None);
+
+        let mut heard_correctly = 0;
+        for _ in 0..100 {
+            let result = env.step(&TigerAction::Listen).unwrap();
+            assert_eq!(result.reward, -1.0);
+            let observation = result.state.unwrap();
+            let expected = match env.hidden_state() {
+                TigerState::Left => TigerObservation::HearLeft,
+                TigerState::Right => TigerObservation::HearRight,
+            };
+            heard_correctly += (observation == expected) as usize;
+            filter.update(TigerAction::Listen, observation).unwrap();
+        }
+        assert!((70..=95).contains(&heard_correctly));
+        // a hundred noisy observations leave little doubt
+        assert!(filter.belief()[&env.hidden_state()] > 0.999);
+
+        assert_eq!(*env.reset(), None);
+    }
+}
@@ -0,0 +1,222 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::{
+    mdp::MDP,
+    solution::{Solution, SolverResult},
+    stopping::{ConvergenceError, StoppingCriteria},
+    tabular::TabularMDP,
+};
+
+/// A state waiting to be backed up, ordered by its Bellman error.
+struct Entry {
+    priority: f64,
+    state: usize,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // ties go to the lower state index so runs are deterministic
+        self.priority
+            .total_cmp(&other.priority)
+            .then_with(|| other.state.cmp(&self.state))
+    }
+}
+
+/// Asynchronous value iteration that always backs up the state with the
+/// largest Bellman error next. After a backup only the predecessors of the
+/// changed state can have a new error, so on sparse-reward problems most of
+/// the work a full sweep would do is skipped.
+///
+/// Stops once no state's Bellman error is above the threshold in `stopping`.
+/// Every backup counts as an iteration, so `num_iterations` equals
+/// `num_backups` and `residuals` holds the error each backup removed.
+pub fn prioritized_sweeping<M>(
+    mdp: &M,
+    discount_rate: f64,
+    stopping: impl Into<StoppingCriteria>,
+) -> SolverResult<M>
+where
+    M: MDP,
+{
+    let tabular = TabularMDP::from_mdp(mdp);
+    let predecessors = tabular.predecessors();
+    let stopper = stopping.into().start(discount_rate);
+    let mut state_values = vec![0.0; tabular.num_states()];
+
+    let bellman_error = |s: usize, state_values: &[f64]| {
+        (tabular.max_q_value(s, state_values, discount_rate) - state_values[s]).abs()
+    };
+
+    // queued entries whose priority no longer matches are stale and skipped
+    let mut priorities: Vec<f64> = (0..tabular.num_states())
+        .map(|s| bellman_error(s, &state_values))
+        .map(|priority| {
+            if stopper.is_converged(priority) {
+                0.0
+            } else {
+                priority
+            }
+        })
+        .collect();
+    let mut queue: BinaryHeap<Entry> = priorities
+        .iter()
+        .enumerate()
+        .filter(|&(_, &priority)| priority > 0.0)
+        .map(|(state, &priority)| Entry { priority, state })
+        .collect();
+
+    let mut num_backups = 0;
+    let mut residuals = vec![];
+    let mut reason = None;
+    while let Some(Entry { priority, state: s }) = queue.pop() {
+        if priority != priorities[s] {
+            continue;
+        }
+        if let Some(exhausted) = stopper.exhausted(num_backups) {
+            reason = Some(exhausted);
+            break;
+        }
+
+        let value = tabular.max_q_value(s, &state_values, discount_rate);
+        residuals.push((value - state_values[s]).abs());
+        state_values[s] = value;
+        priorities[s] = 0.0;
+        num_backups += 1;
+
+        for &p in &predecessors[s] {
+            let priority = bellman_error(p, &state_values);
+            if stopper.is_converged(priority) {
+                // leaves any queued entry for `p` stale
+                priorities[p] = 0.0;
+            } else if priority != priorities[p] {
+                priorities[p] = priority;
+                queue.push(Entry { priority, state: p });
+            }
+        }
+    }
+
+    let action_values = tabular.q_values(&state_values, discount_rate);
+    let (policy_rows, _) = tabular.greedy(&action_values);
+    let solution = Solution {
+        policy: tabular.to_policy(&policy_rows),
+        tied_policy: None,
+        state_values: tabular.to_state_values(&state_values),
+        action_values: tabular.to_action_values(&action_values),
+        expected_return: tabular.expected_return(&state_values),
+        num_iterations: num_backups,
+        num_sweeps: 0,
+        num_backups,
+        residuals,
+    };
+    match reason {
+        None => Ok(solution),
+        Some(reason) => Err(ConvergenceError {
+            reason,
+            num_iterations: num_backups,
+            last_iterate: Box::new(solution),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::Reward,
+        grid_world::{GridWorld, GridWorldMDP, FROZEN_LAKE_8X8},
+        mdp::Probability,
+        policy::Policy,
+        policy_iteration::value_iteration,
+        stopping::StopReason,
+    };
+
+    /// From state 0 a coin flip leads to state 1, which ends the episode
+    /// with a reward of 1, or to state 2, which ends it with a reward of -1.
+    /// State 3 is terminal.
+    struct CoinFlip;
+
+    impl MDP for CoinFlip {
+        type State = u8;
+        type Action = ();
+
+        fn get_states(&self) -> &[u8] {
+            &[0, 1, 2, 3]
+        }
+        fn get_actions(&self) -> &[()] {
+            &[()]
+        }
+        fn transition(&self, state: u8, _action: ()) -> &[(u8, Probability)] {
+            match state {
+                0 => &[(1, 0.5), (2, 0.5)],
+                1 | 2 => &[(3, 1.0)],
+                _ => &[],
+            }
+        }
+        fn reward(&self, state: u8, _action: (), _next_state: u8) -> Reward {
+            match state {
+                1 => 1.0,
+                2 => -1.0,
+                _ => 0.0,
+            }
+        }
+    }
+
+    #[test]
+    fn same_policy_with_fewer_backups() {
+        for noise in [0.0, 2.0 / 3.0] {
+            let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, noise, 0.99).unwrap();
+            let mdp = GridWorldMDP::new(grid_world);
+            let vi = value_iteration(&mdp, 0.99, 1e-10).unwrap();
+            let prioritized = prioritized_sweeping(&mdp, 0.99, 1e-10).unwrap();
+
+            for state in mdp.get_states() {
+                assert!((vi.state_value(state) - prioritized.state_value(state)).abs() < 1e-8);
+                if !mdp.is_terminal(*state) {
+                    assert_eq!(
+                        vi.policy.get_action(state),
+                        prioritized.policy.get_action(state)
+                    );
+                }
+            }
+            assert_eq!(prioritized.num_backups, prioritized.residuals.len());
+            // every backup was needed: none changed a value by less than the threshold
+            assert!(prioritized.residuals.iter().all(|&change| change >= 1e-10));
+            assert!(prioritized.num_backups < vi.num_backups / 2);
+        }
+    }
+
+    #[test]
+    fn no_backup_once_the_error_cancels_out() {
+        // backing up state 1 gives state 0 an error that backing up state 2
+        // takes away again, so state 0 never needs a backup
+        let solution = prioritized_sweeping(&CoinFlip, 0.9, 1e-10).unwrap();
+        assert_eq!(solution.num_backups, 2);
+        assert_eq!(solution.state_value(&0), 0.0);
+        assert!(solution.residuals.iter().all(|&change| change >= 1e-10));
+    }
+
+    #[test]
+    fn backup_budget() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 2.0 / 3.0, 0.99).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let criteria = StoppingCriteria::residual(1e-10).with_max_iterations(10);
+        let error = prioritized_sweeping(&mdp, 0.99, criteria).err().unwrap();
+        assert_eq!(error.reason, StopReason::MaxIterations(10));
+        assert_eq!(error.last_iterate.num_backups, 10);
+    }
+}
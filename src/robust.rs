@@ -0,0 +1,319 @@
+use std::hash::Hash;
+
+use crate::{
+    mdp::{Probability, MDP},
+    policy::StochasticPolicy,
+    policy_iteration::{iterate_values, max_difference},
+    solution::{EvaluationResult, SolverResult},
+    stopping::StoppingCriteria,
+    tabular::TabularMDP,
+};
+
+/// The transition probabilities a row may really have, around the nominal
+/// ones given by [`MDP::transition`]. Only the nominal successors may get
+/// probability, so impossible transitions stay impossible.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UncertaintySet {
+    /// Any distribution within L1 distance `radius` of the nominal one
+    L1Ball { radius: f64 },
+    /// Any distribution whose probabilities are each within `radius` of the
+    /// nominal ones
+    Interval { radius: f64 },
+}
+
+impl UncertaintySet {
+    /// The distribution in the set that minimizes the expected `returns`,
+    /// given the nominal probabilities of the same successors.
+    pub fn worst_case(&self, nominal: &[Probability], returns: &[f64]) -> Vec<Probability> {
+        let mut order: Vec<usize> = (0..nominal.len()).collect();
+        order.sort_by(|&i, &j| returns[i].total_cmp(&returns[j]));
+
+        match *self {
+            UncertaintySet::L1Ball { radius } => {
+                // move up to half the radius from the best successors to the worst
+                let mut probs = nominal.to_vec();
+                let Some(&worst) = order.first() else {
+                    return probs;
+                };
+                let mut budget = (radius / 2.0).min(1.0 - probs[worst]).max(0.0);
+                probs[worst] += budget;
+                for &j in order.iter().rev() {
+                    if j == worst || budget <= 0.0 {
+                        break;
+                    }
+                    let moved = budget.min(probs[j]);
+                    probs[j] -= moved;
+                    budget -= moved;
+                }
+                probs
+            }
+            UncertaintySet::Interval { radius } => {
+                // start from the lower bounds and fill up the worst successors first
+                let mut probs: Vec<Probability> =
+                    nominal.iter().map(|&p| (p - radius).max(0.0)).collect();
+                let mut remaining = 1.0 - probs.iter().sum::<f64>();
+                for &j in &order {
+                    let added = remaining.min((nominal[j] + radius).min(1.0) - probs[j]);
+                    if added <= 0.0 {
+                        break;
+                    }
+                    probs[j] += added;
+                    remaining -= added;
+                }
+                probs
+            }
+        }
+    }
+}
+
+/// A tabular MDP with the reward of every successor kept apart, since the
+/// worst case depends on which successors pay what.
+struct RobustModel<S, A> {
+    tabular: TabularMDP<S, A>,
+    /// `rewards[row]` lines up with the successors of the row
+    rewards: Vec<Vec<f64>>,
+    uncertainty: UncertaintySet,
+}
+
+impl<S, A> RobustModel<S, A>
+where
+    S: Copy + Hash + Eq,
+    A: Copy + Hash + Eq,
+{
+    fn new<M>(mdp: &M, uncertainty: UncertaintySet) -> Self
+    where
+        M: MDP<State = S, Action = A>,
+    {
+        let tabular = TabularMDP::from_mdp(mdp);
+        let rewards = (0..tabular.num_states())
+            .flat_map(|s| tabular.rows(s).map(move |row| (s, row)))
+            .map(|(s, row)| {
+                let state = tabular.states()[s];
+                let action = tabular.row_action(row);
+                tabular
+                    .successors(row)
+                    .0
+                    .iter()
+                    .map(|&j| mdp.reward(state, action, tabular.states()[j]))
+                    .collect()
+            })
+            .collect();
+        Self {
+            tabular,
+            rewards,
+            uncertainty,
+        }
+    }
+
+    /// The worst-case Q of a row given state values `values`.
+    fn q_value(&self, row: usize, values: &[f64], discount_rate: f64) -> f64 {
+        let (next_states, probs) = self.tabular.successors(row);
+        let returns: Vec<f64> = next_states
+            .iter()
+            .zip(&self.rewards[row])
+            .map(|(&j, reward)| reward + discount_rate * values[j])
+            .collect();
+        self.uncertainty
+            .worst_case(probs, &returns)
+            .iter()
+            .zip(&returns)
+            .map(|(prob, ret)| prob * ret)
+            .sum()
+    }
+
+    fn q_values(&self, values: &[f64], discount_rate: f64) -> Vec<f64> {
+        (0..self.tabular.num_rows())
+            .map(|row| self.q_value(row, values, discount_rate))
+            .collect()
+    }
+}
+
+/// Value iteration against an adversary that picks the transition
+/// probabilities of every row from `uncertainty` to minimize the value.
+/// The result maximizes the worst-case value, and its state and action
+/// values are worst-case values. With a radius of zero this is
+/// [`value_iteration`](crate::policy_iteration::value_iteration).
+pub fn robust_value_iteration<M>(
+    mdp: &M,
+    discount_rate: f64,
+    uncertainty: UncertaintySet,
+    stopping: impl Into<StoppingCriteria>,
+) -> SolverResult<M>
+where
+    M: MDP,
+{
+    let model = RobustModel::new(mdp, uncertainty);
+    iterate_values(&model.tabular, discount_rate, stopping.into(), |values| {
+        model.q_values(values, discount_rate)
+    })
+}
+
+/// The worst-case values of `policy` when the transition probabilities of
+/// every row may be anywhere in `uncertainty`, and the number of sweeps
+/// taken. The adversary picks the probabilities of each action the policy
+/// mixes independently. The policy is not consulted in states without
+/// actions, and must only pick available actions elsewhere.
+pub fn evaluate_policy_robust<M, P>(
+    mdp: &M,
+    policy: &P,
+    discount_rate: f64,
+    uncertainty: UncertaintySet,
+    stopping: impl Into<StoppingCriteria>,
+) -> EvaluationResult<M::State>
+where
+    M: MDP,
+    P: StochasticPolicy<M::State, M::Action>,
+{
+    let model = RobustModel::new(mdp, uncertainty);
+    let tabular = &model.tabular;
+    let policy = tabular.policy_rows(policy);
+    let stopper = stopping.into().start(discount_rate);
+    let mut state_values_prev = vec![0.0; tabular.num_states()];
+
+    let mut num_iterations = 0;
+    loop {
+        num_iterations += 1;
+
+        let state_values: Vec<f64> = policy
+            .iter()
+            .map(|rows| {
+                rows.iter()
+                    .map(|&(row, prob)| {
+                        prob * model.q_value(row, &state_values_prev, discount_rate)
+                    })
+                    .sum()
+            })
+            .collect();
+        let max_diff = max_difference(&state_values, &state_values_prev);
+
+        let status = stopper.status(num_iterations, max_diff);
+        if !status.is_running() {
+            return status
+                .into_result(num_iterations, tabular.to_state_values(&state_values))
+                .map(|state_values| (state_values, num_iterations));
+        } else {
+            state_values_prev = state_values;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::Reward,
+        grid_world::{GridWorld, GridWorldMDP, FROZEN_LAKE_4X4, FROZEN_LAKE_8X8},
+        mdp::Probability,
+        policy::MDPPolicy,
+        policy_iteration::{evaluate_policy, value_iteration},
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn worst_case_distributions() {
+        let nominal = [0.5, 0.3, 0.2];
+        let returns = [1.0, 0.0, 2.0];
+
+        let ball = UncertaintySet::L1Ball { radius: 0.4 };
+        let probs = ball.worst_case(&nominal, &returns);
+        let expected = [0.5, 0.5, 0.0];
+        assert!(probs
+            .iter()
+            .zip(expected)
+            .all(|(p, e)| (p - e).abs() < 1e-12));
+
+        let interval = UncertaintySet::Interval { radius: 0.1 };
+        let probs = interval.worst_case(&nominal, &returns);
+        let expected = [0.5, 0.4, 0.1];
+        assert!(probs
+            .iter()
+            .zip(expected)
+            .all(|(p, e)| (p - e).abs() < 1e-12));
+
+        // a huge ball puts everything on the worst successor
+        let probs = UncertaintySet::L1Ball { radius: 5.0 }.worst_case(&nominal, &returns);
+        let expected = [0.0, 1.0, 0.0];
+        assert!(probs
+            .iter()
+            .zip(expected)
+            .all(|(p, e)| (p - e).abs() < 1e-12));
+    }
+
+    #[test]
+    fn no_uncertainty_is_value_iteration() {
+        let mdp = GridWorldMDP::new(GridWorld::from_map(&FROZEN_LAKE_4X4, 2.0 / 3.0, 0.9).unwrap());
+        let nominal = value_iteration(&mdp, 0.9, 1e-10).unwrap();
+        for uncertainty in [
+            UncertaintySet::L1Ball { radius: 0.0 },
+            UncertaintySet::Interval { radius: 0.0 },
+        ] {
+            let robust = robust_value_iteration(&mdp, 0.9, uncertainty, 1e-10).unwrap();
+            for state in mdp.get_states() {
+                assert!((robust.state_value(state) - nominal.state_value(state)).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn robust_and_nominal_policies_on_frozen_lake() {
+        let mdp =
+            GridWorldMDP::new(GridWorld::from_map(&FROZEN_LAKE_8X8, 2.0 / 3.0, 0.99).unwrap());
+        let uncertainty = UncertaintySet::L1Ball { radius: 0.2 };
+        let nominal = value_iteration(&mdp, 0.99, 1e-10).unwrap();
+        let robust = robust_value_iteration(&mdp, 0.99, uncertainty, 1e-10).unwrap();
+        assert!(robust.expected_return < nominal.expected_return);
+
+        let worst_case = |policy| {
+            let (values, _) =
+                evaluate_policy_robust(&mdp, policy, 0.99, uncertainty, 1e-10).unwrap();
+            values[&0]
+        };
+        let nominal_value = |policy| {
+            let (values, _) = evaluate_policy(&mdp, policy, 0.99, 1e-10).unwrap();
+            values[&0]
+        };
+
+        // each policy is best at what it optimizes
+        assert!((worst_case(&robust.policy) - robust.state_value(&0)).abs() < 1e-8);
+        assert!(worst_case(&robust.policy) > worst_case(&nominal.policy));
+        assert!(nominal_value(&nominal.policy) > nominal_value(&robust.policy));
+    }
+
+    #[test]
+    #[should_panic(expected = "policy picks a known action")]
+    fn unavailable_actions_are_rejected() {
+        // only moving right is possible, into a terminal state
+        struct OneWay;
+        impl MDP for OneWay {
+            type State = u8;
+            type Action = char;
+            fn get_states(&self) -> &[u8] {
+                &[0, 1]
+            }
+            fn get_actions(&self) -> &[char] {
+                &['l', 'r']
+            }
+            fn available_actions(&self, state: u8) -> &[char] {
+                if state == 0 {
+                    &['r']
+                } else {
+                    &[]
+                }
+            }
+            fn transition(&self, state: u8, _action: char) -> &[(u8, Probability)] {
+                if state == 0 {
+                    &[(1, 1.0)]
+                } else {
+                    &[]
+                }
+            }
+            fn reward(&self, _state: u8, _action: char, _next_state: u8) -> Reward {
+                1.0
+            }
+        }
+
+        let policy = MDPPolicy::<OneWay>::new(HashMap::from([(0, 'l')]));
+        let uncertainty = UncertaintySet::L1Ball { radius: 0.1 };
+        let _ = evaluate_policy_robust(&OneWay, &policy, 0.9, uncertainty, 1e-10);
+    }
+}
@@ -0,0 +1,217 @@
+use ndarray::{s, Array1, Array2, Axis};
+
+const EPSILON: f64 = 1e-9;
+
+/// Optimal primal and dual solutions of a linear program.
+#[derive(Debug, Clone)]
+pub struct LpSolution {
+    pub x: Array1<f64>,
+    /// One dual value (shadow price) per equality constraint
+    pub duals: Array1<f64>,
+    pub objective: f64,
+    pub num_pivots: usize,
+}
+
+/// Solves the standard-form linear program
+///
+///   maximize c·x  subject to  Ax = b, x ≥ 0
+///
+/// with the two-phase tableau simplex method, using Bland's rule so that
+/// degenerate problems cannot cycle.
+pub fn maximize(c: &Array1<f64>, a: &Array2<f64>, b: &Array1<f64>) -> Result<LpSolution, String> {
+    let (m, n) = a.dim();
+    if c.len() != n || b.len() != m {
+        return Err(format!(
+            "dimension mismatch: c has {}, A is {}x{}, b has {}",
+            c.len(),
+            m,
+            n,
+            b.len()
+        ));
+    }
+
+    // rows with a negative right-hand side are negated so the artificial basis is feasible
+    let signs: Array1<f64> = b.mapv(|v| if v < 0.0 { -1.0 } else { 1.0 });
+
+    // [ A | I | b ] with an objective row underneath
+    let mut tableau = Tableau {
+        t: Array2::zeros((m + 1, n + m + 1)),
+        basis: (n..n + m).collect(),
+        num_pivots: 0,
+    };
+    for i in 0..m {
+        for j in 0..n {
+            tableau.t[[i, j]] = signs[i] * a[[i, j]];
+        }
+        tableau.t[[i, n + i]] = 1.0;
+        tableau.t[[i, n + m]] = signs[i] * b[i];
+    }
+
+    // phase one: drive the artificial variables to zero
+    let phase_one_cost: Array1<f64> = (0..n + m).map(|j| if j < n { 0.0 } else { -1.0 }).collect();
+    tableau.set_objective(&phase_one_cost);
+    tableau.optimize(n + m)?;
+    if tableau.objective_value() < -EPSILON * (1.0 + b.iter().map(|v| v.abs()).sum::<f64>()) {
+        return Err("linear program is infeasible".into());
+    }
+    tableau.remove_artificials(n);
+
+    // phase two: optimize the real objective over the original columns
+    let mut phase_two_cost = Array1::zeros(n + m);
+    phase_two_cost.slice_mut(s![..n]).assign(c);
+    tableau.set_objective(&phase_two_cost);
+    tableau.optimize(n)?;
+
+    let mut x = Array1::zeros(n);
+    for (i, &j) in tableau.basis.iter().enumerate() {
+        if j < n {
+            x[j] = tableau.t[[i, n + m]];
+        }
+    }
+
+    // the artificial columns hold B⁻¹, so their reduced costs are c_B B⁻¹
+    let duals = (0..m).map(|i| signs[i] * tableau.t[[m, n + i]]).collect();
+
+    Ok(LpSolution {
+        objective: c.dot(&x),
+        x,
+        duals,
+        num_pivots: tableau.num_pivots,
+    })
+}
+
+struct Tableau {
+    t: Array2<f64>,
+    basis: Vec<usize>,
+    num_pivots: usize,
+}
+
+impl Tableau {
+    fn num_rows(&self) -> usize {
+        self.basis.len()
+    }
+
+    fn rhs_column(&self) -> usize {
+        self.t.ncols() - 1
+    }
+
+    fn objective_value(&self) -> f64 {
+        self.t[[self.num_rows(), self.rhs_column()]]
+    }
+
+    /// Fills the objective row with reduced costs c_B B⁻¹A_j - c_j for the current basis.
+    fn set_objective(&mut self, cost: &Array1<f64>) {
+        let m = self.num_rows();
+        let mut row = -cost.clone();
+        row.append(Axis(0), Array1::zeros(1).view()).unwrap();
+        for i in 0..m {
+            let c_b = cost[self.basis[i]];
+            if c_b != 0.0 {
+                row.scaled_add(c_b, &self.t.row(i));
+            }
+        }
+        self.t.row_mut(m).assign(&row);
+    }
+
+    /// Pivots until no column below `num_columns` has a negative reduced cost.
+    fn optimize(&mut self, num_columns: usize) -> Result<(), String> {
+        let m = self.num_rows();
+        let rhs = self.rhs_column();
+        loop {
+            // Bland's rule: lowest-index improving column...
+            let entering = match (0..num_columns).find(|&j| self.t[[m, j]] < -EPSILON) {
+                Some(j) => j,
+                None => return Ok(()),
+            };
+
+            // ...and the ratio-test tie broken by lowest basic variable
+            let leaving = (0..m)
+                .filter(|&i| self.t[[i, entering]] > EPSILON)
+                .min_by(|&i, &k| {
+                    let ratio_i = self.t[[i, rhs]] / self.t[[i, entering]];
+                    let ratio_k = self.t[[k, rhs]] / self.t[[k, entering]];
+                    ratio_i
+                        .total_cmp(&ratio_k)
+                        .then(self.basis[i].cmp(&self.basis[k]))
+                });
+
+            match leaving {
+                Some(i) => self.pivot(i, entering),
+                None => return Err("linear program is unbounded".into()),
+            }
+        }
+    }
+
+    fn pivot(&mut self, row: usize, col: usize) {
+        let pivot = self.t[[row, col]];
+        self.t.row_mut(row).mapv_inplace(|v| v / pivot);
+        let pivot_row = self.t.row(row).to_owned();
+        for i in 0..self.t.nrows() {
+            if i != row {
+                let factor = self.t[[i, col]];
+                if factor != 0.0 {
+                    self.t.row_mut(i).scaled_add(-factor, &pivot_row);
+                }
+            }
+        }
+        self.basis[row] = col;
+        self.num_pivots += 1;
+    }
+
+    /// Pivots artificial variables still basic (at zero) after phase one out
+    /// of the basis. Rows where that is impossible are redundant constraints.
+    fn remove_artificials(&mut self, num_original: usize) {
+        for i in 0..self.num_rows() {
+            if self.basis[i] >= num_original {
+                if let Some(j) = (0..num_original).find(|&j| self.t[[i, j]].abs() > EPSILON) {
+                    self.pivot(i, j);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn small_program() {
+        // maximize 3x + 5y s.t. x ≤ 4, 2y ≤ 12, 3x + 2y ≤ 18 (with slacks)
+        let c = array![3.0, 5.0, 0.0, 0.0, 0.0];
+        let a = array![
+            [1.0, 0.0, 1.0, 0.0, 0.0],
+            [0.0, 2.0, 0.0, 1.0, 0.0],
+            [3.0, 2.0, 0.0, 0.0, 1.0],
+        ];
+        let b = array![4.0, 12.0, 18.0];
+        let solution = maximize(&c, &a, &b).unwrap();
+
+        assert!((solution.objective - 36.0).abs() < 1e-9);
+        assert!((solution.x[0] - 2.0).abs() < 1e-9);
+        assert!((solution.x[1] - 6.0).abs() < 1e-9);
+        // strong duality: b·y equals the optimal objective
+        assert!((b.dot(&solution.duals) - 36.0).abs() < 1e-9);
+        assert!((&solution.duals - &array![0.0, 1.5, 1.0])
+            .iter()
+            .all(|d| d.abs() < 1e-9));
+    }
+
+    #[test]
+    fn negative_right_hand_side() {
+        // maximize -x - y s.t. x - y = -1  =>  x = 0, y = 1
+        let solution = maximize(&array![-1.0, -1.0], &array![[1.0, -1.0]], &array![-1.0]).unwrap();
+        assert!((solution.objective + 1.0).abs() < 1e-9);
+        assert!((solution.x[1] - 1.0).abs() < 1e-9);
+        assert!((solution.duals[0] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn infeasible_and_unbounded() {
+        // x + y = -1 has no non-negative solution
+        assert!(maximize(&array![1.0, 1.0], &array![[1.0, 1.0]], &array![-1.0]).is_err());
+        // x - y = 0 lets both grow without bound
+        assert!(maximize(&array![1.0, 1.0], &array![[1.0, -1.0]], &array![0.0]).is_err());
+    }
+}
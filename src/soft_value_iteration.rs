@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use crate::{
+    mdp::MDP,
+    policy::StochasticMDPPolicy,
+    policy_iteration::max_difference,
+    solution::ActionValues,
+    stopping::{ConvergenceError, StoppingCriteria},
+    tabular::TabularMDP,
+};
+
+/// The result of entropy-regularized planning at a fixed temperature.
+pub struct SoftSolution<M: MDP> {
+    /// The Boltzmann policy π(a | s) = exp((Q(s, a) - V(s)) / τ)
+    pub policy: StochasticMDPPolicy<M>,
+    /// Soft state values V(s) = τ log Σ_a exp(Q(s, a) / τ)
+    pub state_values: HashMap<M::State, f64>,
+    /// Soft Q-values, the expected reward plus discounted soft value of successors
+    pub action_values: ActionValues<M::State, M::Action>,
+    /// Soft value of the initial state distribution, Σ μ(s) V(s)
+    pub expected_return: f64,
+    pub temperature: f64,
+    pub num_iterations: usize,
+    /// Bellman residual recorded at the end of every iteration
+    pub residuals: Vec<f64>,
+}
+
+impl<M: MDP> SoftSolution<M> {
+    pub fn state_value(&self, state: &M::State) -> f64 {
+        self.state_values[state]
+    }
+
+    pub fn action_value(&self, state: &M::State, action: &M::Action) -> f64 {
+        self.action_values[state][action]
+    }
+
+    pub fn final_residual(&self) -> Option<f64> {
+        self.residuals.last().copied()
+    }
+}
+
+/// A converged soft solution, or the last iterate of one that ran out of budget
+pub type SoftSolverResult<M> = Result<SoftSolution<M>, ConvergenceError<SoftSolution<M>>>;
+
+/// τ log Σ exp(q / τ), shifted by the max so the exponentials cannot overflow.
+fn log_sum_exp(q: &[f64], temperature: f64) -> f64 {
+    let max_q = q.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let total: f64 = q.iter().map(|&q| ((q - max_q) / temperature).exp()).sum();
+    max_q + temperature * total.ln()
+}
+
+/// Value iteration with the max over actions replaced by a log-sum-exp at
+/// `temperature`, which maximizes reward plus `temperature` times the entropy
+/// of the policy. As the temperature approaches zero this becomes
+/// [`value_iteration`](crate::policy_iteration::value_iteration).
+///
+/// Terminal states, as [`MDP::is_terminal`] decides, end the episode with no
+/// entropy left to collect. They are worth nothing, and the Boltzmann policy
+/// has no actions for them.
+pub fn soft_value_iteration<M>(
+    mdp: &M,
+    discount_rate: f64,
+    temperature: f64,
+    stopping: impl Into<StoppingCriteria>,
+) -> SoftSolverResult<M>
+where
+    M: MDP,
+{
+    assert!(temperature > 0.0, "temperature must be positive");
+
+    let tabular = TabularMDP::from_mdp(mdp);
+    let stopper = stopping.into().start(discount_rate);
+    let mut state_values_prev = vec![0.0; tabular.num_states()];
+
+    let mut num_iterations = 0;
+    let mut residuals = vec![];
+
+    loop {
+        num_iterations += 1;
+
+        let action_values = tabular.q_values(&state_values_prev, discount_rate);
+        let state_values: Vec<f64> = (0..tabular.num_states())
+            .map(|s| {
+                let q = &action_values[tabular.rows(s)];
+                if q.is_empty() {
+                    0.0
+                } else {
+                    log_sum_exp(q, temperature)
+                }
+            })
+            .collect();
+
+        let max_diff = max_difference(&state_values, &state_values_prev);
+        residuals.push(max_diff);
+
+        let status = stopper.status(num_iterations, max_diff);
+        if !status.is_running() {
+            let action_values = tabular.to_action_values(&action_values);
+            let solution = SoftSolution {
+                policy: StochasticMDPPolicy::softmax(mdp, &action_values, temperature),
+                state_values: tabular.to_state_values(&state_values),
+                expected_return: tabular.expected_return(&state_values),
+                action_values,
+                temperature,
+                num_iterations,
+                residuals,
+            };
+            return status.into_result(num_iterations, solution);
+        } else {
+            state_values_prev = state_values;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::Reward,
+        grid_world::{GridWorld, GridWorldMDP, FROZEN_LAKE_4X4},
+        mdp::Probability,
+        policy::StochasticPolicy,
+        policy_iteration::value_iteration,
+    };
+
+    #[test]
+    fn soft_bellman_equation() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 2.0 / 3.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let temperature = 0.5;
+        let solution = soft_value_iteration(&mdp, 0.9, temperature, 1e-12).unwrap();
+
+        // V(s) = Σ_a π(a | s) (Q(s, a) - τ log π(a | s)) away from the terminals
+        for &state in mdp.get_states() {
+            if mdp.is_terminal(state) {
+                assert_eq!(solution.state_value(&state), 0.0);
+                assert!(solution.policy.action_probs(&state).is_empty());
+                continue;
+            }
+            let value: f64 = solution
+                .policy
+                .action_probs(&state)
+                .into_iter()
+                .map(|(action, prob)| {
+                    prob * (solution.action_value(&state, &action) - temperature * prob.ln())
+                })
+                .sum();
+            assert!((solution.state_value(&state) - value).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn approaches_hard_max() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 2.0 / 3.0, 0.9).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let hard = value_iteration(&mdp, 0.9, 1e-12).unwrap();
+
+        let mut prev_gap = f64::INFINITY;
+        for temperature in [1.0, 0.1, 0.01, 0.001] {
+            let soft = soft_value_iteration(&mdp, 0.9, temperature, 1e-12).unwrap();
+            let gap = mdp
+                .get_states()
+                .iter()
+                .map(|state| soft.state_value(state) - hard.state_value(state))
+                .fold(0.0, f64::max);
+            // the entropy bonus is worth at most τ log |A| per step
+            assert!(gap <= temperature * 4f64.ln() / (1.0 - 0.9) + 1e-9);
+            assert!(gap < prev_gap);
+            prev_gap = gap;
+        }
+        assert!(prev_gap < 1e-2);
+    }
+
+    #[test]
+    fn leaving_the_state_space_is_not_terminal() {
+        // both actions pay 1 and leave for a state the MDP does not list
+        struct Exit;
+        impl MDP for Exit {
+            type State = u8;
+            type Action = u8;
+            fn get_states(&self) -> &[u8] {
+                &[0]
+            }
+            fn get_actions(&self) -> &[u8] {
+                &[0, 1]
+            }
+            fn transition(&self, _state: u8, _action: u8) -> &[(u8, Probability)] {
+                &[(1, 1.0)]
+            }
+            fn reward(&self, _state: u8, _action: u8, _next_state: u8) -> Reward {
+                1.0
+            }
+        }
+
+        let temperature = 0.5;
+        let solution = soft_value_iteration(&Exit, 0.9, temperature, 1e-12).unwrap();
+        assert_eq!(solution.policy.action_probs(&0), vec![(0, 0.5), (1, 0.5)]);
+        let expected = 1.0 + temperature * 2f64.ln();
+        assert!((solution.state_value(&0) - expected).abs() < 1e-12);
+    }
+}
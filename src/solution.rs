@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use crate::{
+    mdp::MDP,
+    policy::{MDPPolicy, StochasticMDPPolicy},
+    stopping::ConvergenceError,
+};
+
+/// Q(s, a), keyed by state and then action
+pub type ActionValues<S, A> = HashMap<S, HashMap<A, f64>>;
+
+/// V(s), keyed by state
+pub type StateValues<S> = HashMap<S, f64>;
+
+/// The actions of a state whose values are within ε of the best.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptimalActions<A> {
+    /// In the order of `available_actions`
+    pub actions: Vec<A>,
+    /// How much better the best action is than the best of the rest, or
+    /// `None` when every action is optimal. A small gap marks a state where
+    /// a slightly different model could change the policy.
+    pub gap: Option<f64>,
+}
+
+/// Every action within `epsilon` of the best in each state that has
+/// actions, and the action gap. NaN values are never optimal.
+pub fn optimal_actions<M>(
+    mdp: &M,
+    action_values: &ActionValues<M::State, M::Action>,
+    epsilon: f64,
+) -> HashMap<M::State, OptimalActions<M::Action>>
+where
+    M: MDP,
+{
+    mdp.get_states()
+        .iter()
+        .filter_map(|state| {
+            let values = action_values
+                .get(state)
+                .filter(|values| !values.is_empty())?;
+            let actions = mdp.available_actions(*state);
+            let best = values
+                .values()
+                .filter(|value| !value.is_nan())
+                .fold(f64::NEG_INFINITY, |best, &value| best.max(value));
+            let (optimal, rest): (Vec<&M::Action>, Vec<&M::Action>) = actions
+                .iter()
+                .filter(|action| values.contains_key(action))
+                .partition(|action| values[action] >= best - epsilon);
+            let gap = rest
+                .iter()
+                .map(|action| values[action])
+                .filter(|value| !value.is_nan())
+                .reduce(f64::max)
+                .map(|next_best| best - next_best);
+            let optimal_actions = OptimalActions {
+                actions: optimal.into_iter().copied().collect(),
+                gap,
+            };
+            Some((*state, optimal_actions))
+        })
+        .collect()
+}
+
+/// Converged state values and the number of sweeps taken, or the last
+/// values computed before running out of budget
+pub type EvaluationResult<S> = Result<(StateValues<S>, usize), ConvergenceError<StateValues<S>>>;
+
+/// A converged solution, or the last iterate of one that ran out of budget
+pub type SolverResult<M> = Result<Solution<M>, ConvergenceError<Solution<M>>>;
+
+/// Everything a solver computed on its way to a policy, so that values and
+/// convergence can be inspected without re-running it.
+pub struct Solution<M: MDP> {
+    pub policy: MDPPolicy<M>,
+    /// The policy iterated with [`TieBreak::All`](crate::policy_iteration::TieBreak::All),
+    /// uniform over the tied actions of every state, of which `policy` takes
+    /// the first. `None` for every other solver and tie break.
+    pub tied_policy: Option<StochasticMDPPolicy<M>>,
+    pub state_values: HashMap<M::State, f64>,
+    pub action_values: ActionValues<M::State, M::Action>,
+    /// Value of the initial state distribution, Σ μ(s) V(s)
+    pub expected_return: f64,
+    pub num_iterations: usize,
+    /// Full passes of Bellman backups over the state space, zero for
+    /// solvers that back up states one at a time
+    pub num_sweeps: usize,
+    /// Bellman backups of a single state, counting every state of a sweep
+    pub num_backups: usize,
+    /// Bellman residual recorded at the end of every iteration
+    pub residuals: Vec<f64>,
+}
+
+impl<M: MDP> Solution<M> {
+    pub fn state_value(&self, state: &M::State) -> f64 {
+        self.state_values[state]
+    }
+
+    pub fn action_value(&self, state: &M::State, action: &M::Action) -> f64 {
+        self.action_values[state][action]
+    }
+
+    /// Every action within `epsilon` of the best in each non-terminal state,
+    /// where the policy holds just one of them.
+    pub fn optimal_actions(
+        &self,
+        mdp: &M,
+        epsilon: f64,
+    ) -> HashMap<M::State, OptimalActions<M::Action>> {
+        optimal_actions(mdp, &self.action_values, epsilon)
+    }
+
+    pub fn final_residual(&self) -> Option<f64> {
+        self.residuals.last().copied()
+    }
+}
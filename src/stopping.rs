@@ -0,0 +1,268 @@
+use std::fmt::{Debug, Display};
+use std::time::{Duration, Instant};
+
+/// The iteration budget of a run without discounting that was given no
+/// budget of its own, since nothing then guarantees convergence
+pub const DEFAULT_MAX_ITERATIONS: usize = 100_000;
+
+/// When an iterative solver considers its values converged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tolerance {
+    /// Stop once the Bellman residual (largest value change in an
+    /// iteration) falls below this threshold
+    Residual(f64),
+    /// Stop once the greedy policy is guaranteed to be within ε of optimal,
+    /// i.e. the residual is below ε(1 - γ) / 2γ
+    EpsilonOptimal(f64),
+}
+
+/// A convergence tolerance plus optional budgets after which a solver gives
+/// up and reports its last iterate instead of running forever.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StoppingCriteria {
+    pub tolerance: Tolerance,
+    pub max_iterations: Option<usize>,
+    pub time_budget: Option<Duration>,
+}
+
+impl StoppingCriteria {
+    pub fn residual(threshold: f64) -> Self {
+        Self {
+            tolerance: Tolerance::Residual(threshold),
+            max_iterations: None,
+            time_budget: None,
+        }
+    }
+
+    pub fn epsilon_optimal(epsilon: f64) -> Self {
+        Self {
+            tolerance: Tolerance::EpsilonOptimal(epsilon),
+            max_iterations: None,
+            time_budget: None,
+        }
+    }
+
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = Some(max_iterations);
+        self
+    }
+
+    pub fn with_time_budget(mut self, time_budget: Duration) -> Self {
+        self.time_budget = Some(time_budget);
+        self
+    }
+
+    /// The residual below which iteration stops. With no discounting the
+    /// ε-optimality bound is zero and can only be met exactly.
+    pub fn threshold(&self, discount_rate: f64) -> f64 {
+        match self.tolerance {
+            Tolerance::Residual(threshold) => threshold,
+            Tolerance::EpsilonOptimal(epsilon) => {
+                epsilon * (1.0 - discount_rate) / (2.0 * discount_rate)
+            }
+        }
+    }
+
+    /// Starts a run. Without discounting and without any budget, the run is
+    /// capped at [`DEFAULT_MAX_ITERATIONS`] rather than risk spinning forever.
+    pub(crate) fn start(mut self, discount_rate: f64) -> Stopper {
+        if discount_rate >= 1.0 && self.max_iterations.is_none() && self.time_budget.is_none() {
+            self.max_iterations = Some(DEFAULT_MAX_ITERATIONS);
+        }
+        Stopper {
+            threshold: self.threshold(discount_rate),
+            criteria: self,
+            started: Instant::now(),
+        }
+    }
+}
+
+/// A bare number is a residual threshold, with no budget unless the run is
+/// undiscounted, see [`StoppingCriteria::start`].
+impl From<f64> for StoppingCriteria {
+    fn from(threshold: f64) -> Self {
+        Self::residual(threshold)
+    }
+}
+
+/// Tracks one solver run against its stopping criteria.
+pub(crate) struct Stopper {
+    criteria: StoppingCriteria,
+    threshold: f64,
+    started: Instant,
+}
+
+impl Stopper {
+    /// The residual below which iteration stops
+    pub(crate) fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    pub(crate) fn is_converged(&self, residual: f64) -> bool {
+        residual < self.threshold
+    }
+
+    /// Where a run stands after `num_iterations` ending with `residual`. A
+    /// NaN residual can never converge, so the run fails at once.
+    pub(crate) fn status(&self, num_iterations: usize, residual: f64) -> Status {
+        if residual.is_nan() {
+            Status::Exhausted(StopReason::Failed("the residual is NaN".into()))
+        } else if self.is_converged(residual) {
+            Status::Converged
+        } else if let Some(reason) = self.exhausted(num_iterations) {
+            Status::Exhausted(reason)
+        } else {
+            Status::Running
+        }
+    }
+
+    /// Why the solver must give up after `num_iterations`, if it must.
+    pub(crate) fn exhausted(&self, num_iterations: usize) -> Option<StopReason> {
+        if let Some(max_iterations) = self.criteria.max_iterations {
+            if num_iterations >= max_iterations {
+                return Some(StopReason::MaxIterations(max_iterations));
+            }
+        }
+        if let Some(time_budget) = self.criteria.time_budget {
+            if self.started.elapsed() >= time_budget {
+                return Some(StopReason::TimeBudget(time_budget));
+            }
+        }
+        None
+    }
+}
+
+pub(crate) enum Status {
+    Running,
+    Converged,
+    Exhausted(StopReason),
+}
+
+impl Status {
+    pub(crate) fn is_running(&self) -> bool {
+        matches!(self, Status::Running)
+    }
+
+    /// The solver's result once it has stopped running.
+    pub(crate) fn into_result<T>(
+        self,
+        num_iterations: usize,
+        last_iterate: T,
+    ) -> Result<T, ConvergenceError<T>> {
+        match self {
+            Status::Converged => Ok(last_iterate),
+            Status::Exhausted(reason) => Err(ConvergenceError {
+                reason,
+                num_iterations,
+                last_iterate: Box::new(last_iterate),
+            }),
+            Status::Running => unreachable!("solver is still running"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    MaxIterations(usize),
+    TimeBudget(Duration),
+    /// A step of the solver could not be carried out, e.g. a singular
+    /// linear system in exact policy evaluation
+    Failed(String),
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StopReason::MaxIterations(n) => write!(f, "reached the limit of {} iterations", n),
+            StopReason::TimeBudget(budget) => write!(f, "ran out of its {:?} time budget", budget),
+            StopReason::Failed(message) => write!(f, "failed: {}", message),
+        }
+    }
+}
+
+/// A solver stopped before converging. `last_iterate` holds whatever it had
+/// computed so far.
+pub struct ConvergenceError<T> {
+    pub reason: StopReason,
+    pub num_iterations: usize,
+    pub last_iterate: Box<T>,
+}
+
+impl<T> ConvergenceError<T> {
+    /// Converts the last iterate, e.g. from dense arrays back to maps.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> ConvergenceError<U> {
+        ConvergenceError {
+            reason: self.reason,
+            num_iterations: self.num_iterations,
+            last_iterate: Box::new(f(*self.last_iterate)),
+        }
+    }
+}
+
+impl<T> Debug for ConvergenceError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ConvergenceError")
+            .field("reason", &self.reason)
+            .field("num_iterations", &self.num_iterations)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Display for ConvergenceError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "solver did not converge after {} iterations: {}",
+            self.num_iterations, self.reason
+        )
+    }
+}
+
+impl<T> std::error::Error for ConvergenceError<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epsilon_optimal_threshold() {
+        let criteria = StoppingCriteria::epsilon_optimal(0.1);
+        assert!((criteria.threshold(0.9) - 0.1 * 0.1 / 1.8).abs() < 1e-15);
+        assert_eq!(criteria.threshold(1.0), 0.0);
+        assert_eq!(StoppingCriteria::from(1e-3).threshold(0.5), 1e-3);
+    }
+
+    #[test]
+    fn iteration_budget() {
+        let stopper = StoppingCriteria::residual(1e-3)
+            .with_max_iterations(10)
+            .start(0.9);
+        assert_eq!(stopper.exhausted(9), None);
+        assert_eq!(stopper.exhausted(10), Some(StopReason::MaxIterations(10)));
+        assert!(stopper.is_converged(1e-4));
+        assert!(!stopper.is_converged(1e-3));
+        assert!(matches!(
+            stopper.status(1, f64::NAN),
+            Status::Exhausted(StopReason::Failed(_))
+        ));
+    }
+
+    #[test]
+    fn undiscounted_runs_get_a_budget() {
+        let stopper = StoppingCriteria::from(1e-3).start(1.0);
+        assert_eq!(
+            stopper.exhausted(DEFAULT_MAX_ITERATIONS),
+            Some(StopReason::MaxIterations(DEFAULT_MAX_ITERATIONS))
+        );
+        assert_eq!(
+            StoppingCriteria::from(1e-3)
+                .start(0.99)
+                .exhausted(usize::MAX),
+            None
+        );
+        let stopper = StoppingCriteria::from(1e-3)
+            .with_time_budget(Duration::from_secs(60))
+            .start(1.0);
+        assert_eq!(stopper.exhausted(DEFAULT_MAX_ITERATIONS), None);
+    }
+}
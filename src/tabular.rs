@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Range;
+
+use crate::{
+    mdp::{Probability, MDP},
+    policy::{MDPPolicy, StochasticMDPPolicy, StochasticPolicy},
+    solution::ActionValues,
+};
+
+/// An MDP with states and actions mapped to dense indices and transitions
+/// stored in compressed sparse row (CSR) form, so solvers can work on flat
+/// arrays instead of hash maps.
+///
+/// Each state owns a contiguous range of rows, one per available action, and
+/// each row owns a contiguous range of transition entries. Terminal states
+/// and states without available actions own no rows. Successors that
+/// are not among the MDP's states keep their reward but are dropped from the
+/// entries, i.e. they are worth nothing afterwards.
+pub struct TabularMDP<S, A> {
+    states: Vec<S>,
+    actions: Vec<A>,
+    state_indices: HashMap<S, usize>,
+    action_indices: HashMap<A, usize>,
+    /// rows of state `s` are `state_rows[s]..state_rows[s + 1]`
+    state_rows: Vec<usize>,
+    /// index into `actions` taken by each row
+    row_actions: Vec<usize>,
+    transitions: Transitions,
+    /// the initial distribution over state indices
+    initial: Vec<(usize, Probability)>,
+}
+
+/// The numeric part of a [`TabularMDP`], free of state and action types so
+/// that sweeps over it can be shared across threads.
+struct Transitions {
+    /// entries of row `r` are `row_entries[r]..row_entries[r + 1]`
+    row_entries: Vec<usize>,
+    next_states: Vec<usize>,
+    probs: Vec<Probability>,
+    /// Σ P(s' | s, a) r(s, a, s') for each row
+    expected_rewards: Vec<f64>,
+}
+
+impl Transitions {
+    fn q_value(&self, row: usize, values: &[f64], discount_rate: f64) -> f64 {
+        let entries = self.row_entries[row]..self.row_entries[row + 1];
+        let future: f64 = self.next_states[entries.clone()]
+            .iter()
+            .zip(&self.probs[entries])
+            .map(|(&j, &prob)| prob * values[j])
+            .sum();
+        self.expected_rewards[row] + discount_rate * future
+    }
+}
+
+/// Maps `f` over `0..n`, spread across threads with the `parallel` feature.
+/// Each element is computed the same way either way, so results match exactly.
+fn map_indices<T, F>(n: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Send + Sync,
+{
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        (0..n).into_par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        (0..n).map(f).collect()
+    }
+}
+
+impl<S, A> TabularMDP<S, A>
+where
+    S: Copy + Hash + Eq,
+    A: Copy + Hash + Eq,
+{
+    pub fn from_mdp<M>(mdp: &M) -> Self
+    where
+        M: MDP<State = S, Action = A>,
+    {
+        let states = mdp.get_states().to_vec();
+        let actions = mdp.get_actions().to_vec();
+        let state_indices: HashMap<S, usize> =
+            states.iter().enumerate().map(|(i, &s)| (s, i)).collect();
+        let action_indices: HashMap<A, usize> =
+            actions.iter().enumerate().map(|(i, &a)| (a, i)).collect();
+
+        let mut state_rows = vec![0];
+        let mut row_actions = vec![];
+        let mut row_entries = vec![0];
+        let mut next_states = vec![];
+        let mut probs = vec![];
+        let mut expected_rewards = vec![];
+
+        for &state in &states {
+            let actions: &[A] = if mdp.is_terminal(state) {
+                &[]
+            } else {
+                mdp.available_actions(state)
+            };
+            for &action in actions {
+                let a = *action_indices
+                    .get(&action)
+                    .expect("available actions are among get_actions");
+                let mut expected_reward = 0.0;
+                for &(next_state, prob) in mdp.transition(state, action) {
+                    expected_reward += prob * mdp.reward(state, action, next_state);
+                    if let Some(&j) = state_indices.get(&next_state) {
+                        next_states.push(j);
+                        probs.push(prob);
+                    }
+                }
+                row_actions.push(a);
+                row_entries.push(next_states.len());
+                expected_rewards.push(expected_reward);
+            }
+            state_rows.push(row_actions.len());
+        }
+
+        let initial = mdp
+            .initial_distribution()
+            .into_iter()
+            .filter_map(|(state, prob)| Some((*state_indices.get(&state)?, prob)))
+            .collect();
+
+        Self {
+            states,
+            actions,
+            state_indices,
+            action_indices,
+            initial,
+            state_rows,
+            row_actions,
+            transitions: Transitions {
+                row_entries,
+                next_states,
+                probs,
+                expected_rewards,
+            },
+        }
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.row_actions.len()
+    }
+
+    pub fn states(&self) -> &[S] {
+        &self.states
+    }
+
+    pub fn actions(&self) -> &[A] {
+        &self.actions
+    }
+
+    pub fn state_index(&self, state: &S) -> Option<usize> {
+        self.state_indices.get(state).copied()
+    }
+
+    /// The state-action rows belonging to state `s`.
+    pub fn rows(&self, s: usize) -> Range<usize> {
+        self.state_rows[s]..self.state_rows[s + 1]
+    }
+
+    pub fn row_action(&self, row: usize) -> A {
+        self.actions[self.row_actions[row]]
+    }
+
+    /// The row for taking `action` in state `s`, if it exists.
+    pub fn row(&self, s: usize, action: &A) -> Option<usize> {
+        let a = *self.action_indices.get(action)?;
+        self.rows(s).find(|&row| self.row_actions[row] == a)
+    }
+
+    /// Successor state indices and their probabilities for a row.
+    pub fn successors(&self, row: usize) -> (&[usize], &[Probability]) {
+        let transitions = &self.transitions;
+        let entries = transitions.row_entries[row]..transitions.row_entries[row + 1];
+        (
+            &transitions.next_states[entries.clone()],
+            &transitions.probs[entries],
+        )
+    }
+
+    pub fn expected_reward(&self, row: usize) -> f64 {
+        self.transitions.expected_rewards[row]
+    }
+
+    /// Q for one row given state values `values`.
+    pub fn q_value(&self, row: usize, values: &[f64], discount_rate: f64) -> f64 {
+        self.transitions.q_value(row, values, discount_rate)
+    }
+
+    /// Q for every row given state values `values`.
+    pub fn q_values(&self, values: &[f64], discount_rate: f64) -> Vec<f64> {
+        let transitions = &self.transitions;
+        map_indices(self.num_rows(), |row| {
+            transitions.q_value(row, values, discount_rate)
+        })
+    }
+
+    /// One synchronous application of a policy's Bellman operator, where
+    /// `policy[s]` lists the rows taken in state `s` with their probabilities.
+    pub fn policy_values(
+        &self,
+        policy: &[Vec<(usize, Probability)>],
+        values: &[f64],
+        discount_rate: f64,
+    ) -> Vec<f64> {
+        let transitions = &self.transitions;
+        map_indices(policy.len(), |s| {
+            policy[s]
+                .iter()
+                .map(|&(row, action_prob)| {
+                    action_prob * transitions.q_value(row, values, discount_rate)
+                })
+                .sum()
+        })
+    }
+
+    /// Whether no action in state `s` leads anywhere, i.e. episodes end there.
+    pub fn is_terminal(&self, s: usize) -> bool {
+        self.rows(s).all(|row| self.successors(row).0.is_empty())
+    }
+
+    /// The highest Q over the rows of state `s`, i.e. its Bellman backup.
+    /// A state without actions is worth nothing.
+    pub fn max_q_value(&self, s: usize, values: &[f64], discount_rate: f64) -> f64 {
+        self.rows(s)
+            .map(|row| self.q_value(row, values, discount_rate))
+            .reduce(f64::max)
+            .unwrap_or(0.0)
+    }
+
+    /// The reverse transition index: the states that can reach each state in
+    /// one step under some action, without duplicates.
+    pub fn predecessors(&self) -> Vec<Vec<usize>> {
+        let mut predecessors = vec![vec![]; self.num_states()];
+        for s in 0..self.num_states() {
+            for row in self.rows(s) {
+                for &j in self.successors(row).0 {
+                    if predecessors[j].last() != Some(&s) {
+                        predecessors[j].push(s);
+                    }
+                }
+            }
+        }
+        predecessors
+    }
+
+    /// The first row with the highest Q in every state, and that Q. States
+    /// without actions have no row and are worth nothing. NaN never beats a
+    /// number, so it is only chosen when every row is NaN.
+    pub fn greedy(&self, q: &[f64]) -> (Vec<Option<usize>>, Vec<f64>) {
+        (0..self.num_states())
+            .map(|s| {
+                let mut best_row = None;
+                for row in self.rows(s) {
+                    if best_row
+                        .is_none_or(|best_row: usize| q[row] > q[best_row] || q[best_row].is_nan())
+                    {
+                        best_row = Some(row);
+                    }
+                }
+                (best_row, best_row.map_or(0.0, |row| q[row]))
+            })
+            .unzip()
+    }
+
+    /// The rows of state `s` whose Q is within `tolerance` of the highest,
+    /// in row order, and the highest Q. NaN rows are never within tolerance;
+    /// if every row is NaN the first is kept and the state is worth NaN.
+    pub fn near_greedy(&self, s: usize, q: &[f64], tolerance: f64) -> (Vec<usize>, f64) {
+        let rows = self.rows(s);
+        if rows.is_empty() {
+            return (vec![], 0.0);
+        }
+        let best = rows
+            .clone()
+            .map(|row| q[row])
+            .filter(|value| !value.is_nan())
+            .fold(f64::NEG_INFINITY, f64::max);
+        let near: Vec<usize> = rows
+            .clone()
+            .filter(|&row| q[row] >= best - tolerance)
+            .collect();
+        if near.is_empty() {
+            (vec![rows.start], f64::NAN)
+        } else {
+            (near, best)
+        }
+    }
+
+    /// The rows a policy uses in every state, with their probabilities. The
+    /// policy is not consulted in states without rows.
+    pub fn policy_rows<P>(&self, policy: &P) -> Vec<Vec<(usize, Probability)>>
+    where
+        P: StochasticPolicy<S, A>,
+    {
+        self.states
+            .iter()
+            .enumerate()
+            .map(|(s, state)| {
+                if self.rows(s).is_empty() {
+                    return vec![];
+                }
+                policy
+                    .action_probs(state)
+                    .into_iter()
+                    .map(|(action, prob)| {
+                        let row = self.row(s, &action).expect("policy picks a known action");
+                        (row, prob)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The expected value of `values` under the initial distribution.
+    pub fn expected_return(&self, values: &[f64]) -> f64 {
+        self.initial.iter().map(|&(s, prob)| prob * values[s]).sum()
+    }
+
+    pub fn to_state_values(&self, values: &[f64]) -> HashMap<S, f64> {
+        self.states
+            .iter()
+            .copied()
+            .zip(values.iter().copied())
+            .collect()
+    }
+
+    pub fn to_action_values(&self, q: &[f64]) -> ActionValues<S, A> {
+        self.states
+            .iter()
+            .enumerate()
+            .map(|(s, &state)| {
+                let action_values = self
+                    .rows(s)
+                    .map(|row| (self.row_action(row), q[row]))
+                    .collect();
+                (state, action_values)
+            })
+            .collect()
+    }
+
+    /// The action of the given row in every state that has one.
+    pub fn to_actions(&self, rows: &[Option<usize>]) -> HashMap<S, A> {
+        self.states
+            .iter()
+            .zip(rows)
+            .filter_map(|(&state, &row)| Some((state, self.row_action(row?))))
+            .collect()
+    }
+
+    /// The deterministic policy taking the given row in every state that has one.
+    pub fn to_policy<M>(&self, rows: &[Option<usize>]) -> MDPPolicy<M>
+    where
+        M: MDP<State = S, Action = A>,
+    {
+        MDPPolicy::new(self.to_actions(rows))
+    }
+
+    /// The stochastic policy taking the given rows with their probabilities,
+    /// indexed like the states, as [`policy_rows`](Self::policy_rows) lists them.
+    pub fn to_stochastic_policy<M>(
+        &self,
+        policy: &[Vec<(usize, Probability)>],
+    ) -> StochasticMDPPolicy<M>
+    where
+        M: MDP<State = S, Action = A>,
+    {
+        StochasticMDPPolicy::new(
+            self.states
+                .iter()
+                .zip(policy)
+                .map(|(&state, rows)| {
+                    let action_probs = rows
+                        .iter()
+                        .map(|&(row, prob)| (self.row_action(row), prob))
+                        .collect();
+                    (state, action_probs)
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        direction::Direction,
+        grid_world::{GridWorld, GridWorldMDP, FROZEN_LAKE_4X4, FROZEN_LAKE_8X8},
+        policy::StochasticMDPPolicy,
+    };
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn csr_layout() {
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_4X4, 2.0 / 3.0, 1.0).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let tabular = TabularMDP::from_mdp(&mdp);
+
+        assert_eq!(tabular.num_states(), 16);
+        // the four holes and the goal are terminal and own no rows
+        assert_eq!(tabular.num_rows(), 44);
+        assert_eq!(tabular.rows(3), 12..16);
+        assert!(tabular.rows(5).is_empty());
+
+        // every row of a non-terminal state is a distribution matching the MDP
+        for s in 0..16 {
+            for row in tabular.rows(s) {
+                let action = tabular.row_action(row);
+                let (next_states, probs) = tabular.successors(row);
+                assert_eq!(next_states.len(), mdp.transition(s, action).len());
+                for (&j, &prob) in next_states.iter().zip(probs) {
+                    let expected: f64 = mdp
+                        .transition(s, action)
+                        .iter()
+                        .filter(|&&(next, _)| next == j)
+                        .map(|&(_, p)| p)
+                        .sum();
+                    assert_eq!(prob, expected);
+                }
+            }
+        }
+
+        // moving right from 14 reaches the goal a third of the time
+        let row = tabular.row(14, &Direction::Right).unwrap();
+        assert!((tabular.expected_reward(row) - 1.0 / 3.0).abs() < 1e-12);
+        assert_eq!(tabular.row(15, &Direction::Up), None);
+        assert!(tabular.is_terminal(15));
+
+        // episodes start in the top left corner
+        let mut values = vec![0.0; 16];
+        values[0] = 2.0;
+        assert_eq!(tabular.expected_return(&values), 2.0);
+    }
+
+    #[test]
+    fn sweeps_match_row_by_row() {
+        // with the `parallel` feature this checks the threaded sweeps
+        let grid_world = GridWorld::from_map(&FROZEN_LAKE_8X8, 2.0 / 3.0, 0.99).unwrap();
+        let mdp = GridWorldMDP::new(grid_world);
+        let tabular = TabularMDP::from_mdp(&mdp);
+        let mut rng = StdRng::seed_from_u64(0);
+        let values: Vec<f64> = (0..tabular.num_states()).map(|_| rng.gen()).collect();
+
+        // a serial reference straight from the MDP, whose states are indices
+        let mut reference = vec![];
+        for &s in mdp.get_states() {
+            for row in tabular.rows(s) {
+                let action = tabular.row_action(row);
+                let mut q = 0.0;
+                for &(next_state, prob) in mdp.transition(s, action) {
+                    q += prob * (mdp.reward(s, action, next_state) + 0.99 * values[next_state]);
+                }
+                reference.push(q);
+            }
+        }
+
+        let q = tabular.q_values(&values, 0.99);
+        assert_eq!(q.len(), reference.len());
+        for (&q, &expected) in q.iter().zip(&reference) {
+            assert!((q - expected).abs() < 1e-12);
+        }
+
+        let (greedy_rows, greedy_values) = tabular.greedy(&q);
+        for s in 0..tabular.num_states() {
+            let mut best: Option<usize> = None;
+            for row in tabular.rows(s) {
+                if best.is_none_or(|best| reference[row] > reference[best] + 1e-12) {
+                    best = Some(row);
+                }
+            }
+            assert_eq!(greedy_rows[s], best);
+            let expected = best.map_or(0.0, |row| reference[row]);
+            assert!((greedy_values[s] - expected).abs() < 1e-12);
+        }
+
+        let policy = tabular.policy_rows(&StochasticMDPPolicy::uniform(&mdp));
+        let swept = tabular.policy_values(&policy, &values, 0.99);
+        for (s, &value) in swept.iter().enumerate() {
+            let expected: f64 = tabular.rows(s).map(|row| 0.25 * reference[row]).sum();
+            assert!((value - expected).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt::Display;
+
+use crate::{
+    environment::Reward,
+    mdp::{Probability, MDP},
+    pomdp::POMDP,
+};
+
+/// Where the tiger is hiding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TigerState {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TigerAction {
+    Listen,
+    OpenLeft,
+    OpenRight,
+}
+
+impl Display for TigerAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            TigerAction::Listen => "listen",
+            TigerAction::OpenLeft => "open left",
+            TigerAction::OpenRight => "open right",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Which side the tiger was heard on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TigerObservation {
+    HearLeft,
+    HearRight,
+}
+
+static STATES: [TigerState; 2] = [TigerState::Left, TigerState::Right];
+static ACTIONS: [TigerAction; 3] = [
+    TigerAction::Listen,
+    TigerAction::OpenLeft,
+    TigerAction::OpenRight,
+];
+static OBSERVATIONS: [TigerObservation; 2] =
+    [TigerObservation::HearLeft, TigerObservation::HearRight];
+
+/// Probability of hearing the tiger on the side it is on
+pub const LISTEN_ACCURACY: Probability = 0.85;
+
+/// The tiger problem of Kaelbling, Littman and Cassandra (1998). A tiger
+/// hides behind one of two doors and treasure behind the other. Listening
+/// costs 1 and hears the tiger on the right side with probability 0.85.
+/// Opening the tiger's door costs 100 and the other door pays 10; either
+/// way the tiger hides again behind a random door.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tiger;
+
+impl MDP for Tiger {
+    type State = TigerState;
+    type Action = TigerAction;
+
+    fn get_states(&self) -> &[TigerState] {
+        &STATES
+    }
+
+    fn get_actions(&self) -> &[TigerAction] {
+        &ACTIONS
+    }
+
+    fn transition(&self, state: TigerState, action: TigerAction) -> &[(TigerState, Probability)] {
+        match (action, state) {
+            (TigerAction::Listen, TigerState::Left) => &[(TigerState::Left, 1.0)],
+            (TigerAction::Listen, TigerState::Right) => &[(TigerState::Right, 1.0)],
+            _ => &[(TigerState::Left, 0.5), (TigerState::Right, 0.5)],
+        }
+    }
+
+    fn reward(&self, state: TigerState, action: TigerAction, _next_state: TigerState) -> Reward {
+        match (action, state) {
+            (TigerAction::Listen, _) => -1.0,
+            (TigerAction::OpenLeft, TigerState::Left)
+            | (TigerAction::OpenRight, TigerState::Right) => -100.0,
+            _ => 10.0,
+        }
+    }
+}
+
+impl POMDP for Tiger {
+    type Observation = TigerObservation;
+
+    fn get_observations(&self) -> &[TigerObservation] {
+        &OBSERVATIONS
+    }
+
+    fn observation(
+        &self,
+        action: TigerAction,
+        next_state: TigerState,
+    ) -> &[(TigerObservation, Probability)] {
+        match (action, next_state) {
+            (TigerAction::Listen, TigerState::Left) => &[
+                (TigerObservation::HearLeft, LISTEN_ACCURACY),
+                (TigerObservation::HearRight, 1.0 - LISTEN_ACCURACY),
+            ],
+            (TigerAction::Listen, TigerState::Right) => &[
+                (TigerObservation::HearLeft, 1.0 - LISTEN_ACCURACY),
+                (TigerObservation::HearRight, LISTEN_ACCURACY),
+            ],
+            _ => &[
+                (TigerObservation::HearLeft, 0.5),
+                (TigerObservation::HearRight, 0.5),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::validate;
+
+    #[test]
+    fn tiger_is_a_valid_pomdp() {
+        assert!(validate(&Tiger).is_valid());
+        for &action in Tiger.get_actions() {
+            for &next_state in Tiger.get_states() {
+                let total: Probability = Tiger
+                    .observation(action, next_state)
+                    .iter()
+                    .map(|&(_, prob)| prob)
+                    .sum();
+                assert!((total - 1.0).abs() < 1e-12);
+            }
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::HashSet;
+use std::fmt::{Debug, Display};
+
+use crate::{
+    environment::Reward,
+    mdp::{Probability, MDP},
+};
+
+/// How far the probabilities of a transition may sum from one.
+pub const PROBABILITY_TOLERANCE: f64 = 1e-9;
+
+/// Something wrong with the model an [`MDP`] implementation describes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem<S, A> {
+    /// An available action that is not among `get_actions`
+    UnknownAction {
+        state: S,
+        action: A,
+    },
+    /// A probability that is negative or NaN
+    InvalidProbability {
+        state: S,
+        action: A,
+        next_state: S,
+        prob: Probability,
+    },
+    /// The probabilities of a transition do not sum to one
+    ProbabilityMass {
+        state: S,
+        action: A,
+        total: f64,
+    },
+    /// A successor that is not among `get_states`
+    UnknownSuccessor {
+        state: S,
+        action: A,
+        next_state: S,
+    },
+    /// The same successor listed more than once in a transition
+    DuplicateSuccessor {
+        state: S,
+        action: A,
+        next_state: S,
+    },
+    NanReward {
+        state: S,
+        action: A,
+        next_state: S,
+    },
+    /// An action with no transitions in a state that is not terminal
+    DeadEnd {
+        state: S,
+        action: A,
+    },
+    /// A state of the initial distribution that is not among `get_states`
+    UnknownInitialState {
+        state: S,
+    },
+    /// The initial distribution is not a probability distribution
+    InitialMass {
+        total: f64,
+    },
+}
+
+impl<S: Debug, A: Debug> Display for Problem<S, A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Problem::UnknownAction { state, action } => write!(
+                f,
+                "{:?} offers {:?}, which is not a known action",
+                state, action
+            ),
+            Problem::InvalidProbability {
+                state,
+                action,
+                next_state,
+                prob,
+            } => write!(
+                f,
+                "{:?} --{:?}--> {:?} has invalid probability {}",
+                state, action, next_state, prob
+            ),
+            Problem::ProbabilityMass {
+                state,
+                action,
+                total,
+            } => write!(
+                f,
+                "{:?} --{:?}--> probabilities sum to {}",
+                state, action, total
+            ),
+            Problem::UnknownSuccessor {
+                state,
+                action,
+                next_state,
+            } => write!(
+                f,
+                "{:?} --{:?}--> {:?} is not a known state",
+                state, action, next_state
+            ),
+            Problem::DuplicateSuccessor {
+                state,
+                action,
+                next_state,
+            } => write!(
+                f,
+                "{:?} --{:?}--> {:?} is listed more than once",
+                state, action, next_state
+            ),
+            Problem::NanReward {
+                state,
+                action,
+                next_state,
+            } => write!(
+                f,
+                "{:?} --{:?}--> {:?} has a NaN reward",
+                state, action, next_state
+            ),
+            Problem::DeadEnd { state, action } => write!(
+                f,
+                "{:?} --{:?}--> has no transitions but the state is not terminal",
+                state, action
+            ),
+            Problem::UnknownInitialState { state } => {
+                write!(f, "initial state {:?} is not a known state", state)
+            }
+            Problem::InitialMass { total } => {
+                write!(f, "initial probabilities sum to {}", total)
+            }
+        }
+    }
+}
+
+/// Every problem found in a model, in state-action order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationReport<S, A> {
+    pub problems: Vec<Problem<S, A>>,
+}
+
+impl<S, A> ValidationReport<S, A> {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl<S: Debug, A: Debug> Display for ValidationReport<S, A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.is_valid() {
+            return writeln!(f, "no problems found");
+        }
+        writeln!(f, "{} problems found:", self.problems.len())?;
+        for problem in &self.problems {
+            writeln!(f, "  {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// False for NaN totals as well as ones too far from one.
+fn sums_to_one(total: f64) -> bool {
+    (total - 1.0).abs() <= PROBABILITY_TOLERANCE
+}
+
+/// Checks that every transition of `mdp` is a probability distribution over
+/// known states with well-defined rewards. Solvers assume this without
+/// checking; e.g. unknown successors are silently treated as worth nothing.
+pub fn validate<M>(mdp: &M) -> ValidationReport<M::State, M::Action>
+where
+    M: MDP,
+{
+    let states: HashSet<M::State> = mdp.get_states().iter().copied().collect();
+    let actions: HashSet<M::Action> = mdp.get_actions().iter().copied().collect();
+    let mut problems = vec![];
+
+    for &state in mdp.get_states() {
+        let is_terminal = mdp.is_terminal(state);
+
+        for &action in mdp.available_actions(state) {
+            if !actions.contains(&action) {
+                problems.push(Problem::UnknownAction { state, action });
+            }
+
+            let transitions = mdp.transition(state, action);
+            if transitions.is_empty() {
+                if !is_terminal {
+                    problems.push(Problem::DeadEnd { state, action });
+                }
+                continue;
+            }
+
+            let mut seen = HashSet::new();
+            let mut total = 0.0;
+            for &(next_state, prob) in transitions {
+                if prob.is_nan() || prob < 0.0 {
+                    problems.push(Problem::InvalidProbability {
+                        state,
+                        action,
+                        next_state,
+                        prob,
+                    });
+                }
+                total += prob;
+
+                if !states.contains(&next_state) {
+                    problems.push(Problem::UnknownSuccessor {
+                        state,
+                        action,
+                        next_state,
+                    });
+                }
+                if !seen.insert(next_state) {
+                    problems.push(Problem::DuplicateSuccessor {
+                        state,
+                        action,
+                        next_state,
+                    });
+                }
+                let reward: Reward = mdp.reward(state, action, next_state);
+                if reward.is_nan() {
+                    problems.push(Problem::NanReward {
+                        state,
+                        action,
+                        next_state,
+                    });
+                }
+            }
+
+            if !sums_to_one(total) {
+                problems.push(Problem::ProbabilityMass {
+                    state,
+                    action,
+                    total,
+                });
+            }
+        }
+    }
+
+    let mut total = 0.0;
+    let mut valid_probs = true;
+    for (state, prob) in mdp.initial_distribution() {
+        if !states.contains(&state) {
+            problems.push(Problem::UnknownInitialState { state });
+        }
+        valid_probs &= prob >= 0.0;
+        total += prob;
+    }
+    if !valid_probs || !sums_to_one(total) {
+        problems.push(Problem::InitialMass { total });
+    }
+
+    ValidationReport { problems }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid_world::{GridWorld, GridWorldMDP, FROZEN_LAKE_4X4, FROZEN_LAKE_8X8};
+
+    /// States 0 and 1 with two actions, and a transition table with
+    /// something wrong in every entry but the first. State 1 also offers an
+    /// action that does not exist, and episodes start in a state that does not.
+    struct Broken;
+
+    impl MDP for Broken {
+        type State = u8;
+        type Action = char;
+
+        fn get_states(&self) -> &[u8] {
+            &[0, 1]
+        }
+        fn get_actions(&self) -> &[char] {
+            &['a', 'b']
+        }
+        fn available_actions(&self, state: u8) -> &[char] {
+            match state {
+                0 => &['a', 'b'],
+                _ => &['a', 'b', 'c'],
+            }
+        }
+        fn transition(&self, state: u8, action: char) -> &[(u8, Probability)] {
+            match (state, action) {
+                (0, 'a') => &[(0, 0.5), (1, 0.5)],
+                (0, 'b') => &[(1, 0.5), (1, 0.5), (2, 0.0)],
+                (1, 'a') => &[(0, 1.2), (1, -0.1)],
+                _ => &[],
+            }
+        }
+        fn initial_distribution(&self) -> Vec<(u8, Probability)> {
+            vec![(0, 0.5), (3, 0.25)]
+        }
+        fn reward(&self, state: u8, _action: char, next_state: u8) -> Reward {
+            if state == 1 && next_state == 1 {
+                f64::NAN
+            } else {
+                0.0
+            }
+        }
+    }
+
+    #[test]
+    fn grid_worlds_are_valid() {
+        for noise in [0.0, 2.0 / 3.0] {
+            let mdp = GridWorldMDP::new(GridWorld::from_map(&FROZEN_LAKE_4X4, noise, 0.9).unwrap());
+            assert!(validate(&mdp).is_valid());
+            let mdp = GridWorldMDP::new(GridWorld::from_map(&FROZEN_LAKE_8X8, noise, 0.9).unwrap());
+            assert!(validate(&mdp).is_valid());
+        }
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let report = validate(&Broken);
+        assert_eq!(
+            report.problems,
+            vec![
+                Problem::DuplicateSuccessor {
+                    state: 0,
+                    action: 'b',
+                    next_state: 1
+                },
+                Problem::UnknownSuccessor {
+                    state: 0,
+                    action: 'b',
+                    next_state: 2
+                },
+                Problem::InvalidProbability {
+                    state: 1,
+                    action: 'a',
+                    next_state: 1,
+                    prob: -0.1
+                },
+                Problem::NanReward {
+                    state: 1,
+                    action: 'a',
+                    next_state: 1
+                },
+                Problem::ProbabilityMass {
+                    state: 1,
+                    action: 'a',
+                    total: 1.2 - 0.1
+                },
+                Problem::DeadEnd {
+                    state: 1,
+                    action: 'b'
+                },
+                Problem::UnknownAction {
+                    state: 1,
+                    action: 'c'
+                },
+                Problem::DeadEnd {
+                    state: 1,
+                    action: 'c'
+                },
+                Problem::UnknownInitialState { state: 3 },
+                Problem::InitialMass { total: 0.75 },
+            ]
+        );
+        assert!(report.to_string().starts_with("10 problems found:"));
+    }
+}